@@ -0,0 +1,435 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::chip8::{PROGRAM_START, XO_MEMORY_SIZE};
+use crate::instruction::Instruction;
+
+// how deep `define`s may refer to other defines before we assume a cycle
+const MAX_DEFINE_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LINE {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand<'a> {
+    Register(usize),
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    Bcd,
+    BigFont,
+    Flags,
+    Audio,
+    Pitch,
+    Long(&'a str),
+    Value(&'a str),
+}
+
+struct Statement<'a> {
+    line: usize,
+    address: usize,
+    mnemonic: String,
+    operands: Vec<&'a str>,
+}
+
+struct Symbols<'a> {
+    labels: HashMap<&'a str, usize>,
+    defines: HashMap<&'a str, &'a str>,
+}
+
+fn error<T>(line: usize, message: String) -> Result<T, AssembleError> {
+    Err(AssembleError { line, message })
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn parse_register(text: &str) -> Option<usize> {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some('v') | Some('V') if text.len() == 2 => chars.next()?.to_digit(16).map(|x| x as usize),
+        _ => None,
+    }
+}
+
+fn parse_operand(text: &str) -> Operand<'_> {
+    if let Some(x) = parse_register(text) {
+        return Operand::Register(x);
+    }
+    match text.to_ascii_uppercase().as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::DelayTimer,
+        "ST" => Operand::SoundTimer,
+        "K" => Operand::Key,
+        "F" => Operand::Font,
+        "B" => Operand::Bcd,
+        "HF" => Operand::BigFont,
+        "R" => Operand::Flags,
+        "AUDIO" => Operand::Audio,
+        "PITCH" => Operand::Pitch,
+        _ => match text.get(..5).filter(|long| long.eq_ignore_ascii_case("LONG ")) {
+            Some(_) => Operand::Long(text[5..].trim()),
+            None => Operand::Value(text),
+        },
+    }
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x").or_else(|| lower.strip_prefix('#')).or_else(|| lower.strip_prefix('$')) {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b").or_else(|| lower.strip_prefix('%')) {
+        i64::from_str_radix(binary, 2).ok()
+    } else {
+        lower.parse::<i64>().ok()
+    }
+}
+
+impl<'a> Symbols<'a> {
+    // evaluates numbers, labels and defines joined by + and -
+    fn evaluate(&self, expression: &str, line: usize, depth: usize) -> Result<i64, AssembleError> {
+        if depth > MAX_DEFINE_DEPTH {
+            return error(line, format!("DEFINE NESTED TOO DEEPLY IN '{}'", expression));
+        }
+        let mut total = 0;
+        let mut sign = 1;
+        let mut term_start = 0;
+        for (index, c) in expression.char_indices() {
+            if c != '+' && c != '-' {
+                continue;
+            }
+            let term = expression[term_start..index].trim();
+            if term.is_empty() {
+                // a sign in front of a term, e.g. `-1`
+                if c == '-' {
+                    sign = -sign;
+                }
+            } else {
+                total += sign * self.evaluate_term(term, line, depth)?;
+                sign = if c == '-' { -1 } else { 1 };
+            }
+            term_start = index + 1;
+        }
+        let term = expression[term_start..].trim();
+        if term.is_empty() {
+            return error(line, format!("MISSING VALUE IN '{}'", expression.trim()));
+        }
+        total += sign * self.evaluate_term(term, line, depth)?;
+        Ok(total)
+    }
+
+    fn evaluate_term(&self, term: &str, line: usize, depth: usize) -> Result<i64, AssembleError> {
+        if let Some(value) = parse_number(term) {
+            return Ok(value);
+        }
+        if let Some(address) = self.labels.get(term) {
+            return Ok(*address as i64);
+        }
+        if let Some(expression) = self.defines.get(term) {
+            return self.evaluate(expression, line, depth + 1);
+        }
+        error(line, format!("UNKNOWN VALUE '{}'", term))
+    }
+
+    fn value(&self, text: &str, line: usize, max: i64) -> Result<i64, AssembleError> {
+        let value = self.evaluate(text, line, 0)?;
+        if value < 0 || value > max {
+            return error(line, format!("VALUE {} OF '{}' IS OUT OF RANGE (0 - {})", value, text, max));
+        }
+        Ok(value)
+    }
+
+    fn address(&self, text: &str, line: usize) -> Result<u16, AssembleError> {
+        Ok(self.value(text, line, 0xFFF)? as u16)
+    }
+
+    fn long_address(&self, text: &str, line: usize) -> Result<u16, AssembleError> {
+        Ok(self.value(text, line, 0xFFFF)? as u16)
+    }
+
+    fn byte(&self, text: &str, line: usize) -> Result<u8, AssembleError> {
+        // negative bytes are allowed, so `ADD V0, -1` works
+        let value = self.evaluate(text, line, 0)?;
+        if !(-128..=0xFF).contains(&value) {
+            return error(line, format!("VALUE {} OF '{}' DOES NOT FIT IN A BYTE", value, text));
+        }
+        Ok(value as u8)
+    }
+}
+
+fn encode_statement(statement: &Statement, symbols: &Symbols) -> Result<Instruction, AssembleError> {
+    let line = statement.line;
+    let operands: Vec<Operand> = statement.operands.iter().map(|text| parse_operand(text)).collect();
+    let invalid = || error(line, format!("NON VALID OPERANDS FOR {}: {}", statement.mnemonic, statement.operands.join(", ")));
+
+    let instruction = match (statement.mnemonic.as_str(), operands.as_slice()) {
+        ("SCD", [Operand::Value(n)]) => Instruction::ScrollDown(symbols.value(n, line, 0xF)? as u8),
+        ("SCU", [Operand::Value(n)]) => Instruction::ScrollUp(symbols.value(n, line, 0xF)? as u8),
+        ("CLS", []) => Instruction::Cls,
+        ("RET", []) => Instruction::Ret,
+        ("SCR", []) => Instruction::ScrollRight,
+        ("SCL", []) => Instruction::ScrollLeft,
+        ("EXIT", []) => Instruction::Exit,
+        ("LOW", []) => Instruction::LowRes,
+        ("HIGH", []) => Instruction::HighRes,
+        ("JP", [Operand::Value(nnn)]) => Instruction::Jp(symbols.address(nnn, line)?),
+        ("JP", [Operand::Register(0), Operand::Value(nnn)]) => Instruction::JpV0(symbols.address(nnn, line)?),
+        ("CALL", [Operand::Value(nnn)]) => Instruction::Call(symbols.address(nnn, line)?),
+        ("SE", [Operand::Register(x), Operand::Register(y)]) => Instruction::SeReg(*x, *y),
+        ("SE", [Operand::Register(x), Operand::Value(nn)]) => Instruction::SeByte(*x, symbols.byte(nn, line)?),
+        ("SAVE", [Operand::Register(x), Operand::Register(y)]) => Instruction::StoreRange(*x, *y),
+        ("LOAD", [Operand::Register(x), Operand::Register(y)]) => Instruction::LoadRange(*x, *y),
+        ("SNE", [Operand::Register(x), Operand::Register(y)]) => Instruction::SneReg(*x, *y),
+        ("SNE", [Operand::Register(x), Operand::Value(nn)]) => Instruction::SneByte(*x, symbols.byte(nn, line)?),
+        ("LD", [Operand::Register(x), Operand::Register(y)]) => Instruction::LdReg(*x, *y),
+        ("LD", [Operand::Register(x), Operand::Value(nn)]) => Instruction::Ld(*x, symbols.byte(nn, line)?),
+        ("LD", [Operand::I, Operand::Value(nnn)]) => Instruction::LdI(symbols.address(nnn, line)?),
+        ("LD", [Operand::I, Operand::Long(nnnn)]) => Instruction::LdILong(symbols.long_address(nnnn, line)?),
+        ("LD", [Operand::Register(x), Operand::DelayTimer]) => Instruction::LdDelay(*x),
+        ("LD", [Operand::Register(x), Operand::Key]) => Instruction::LdKey(*x),
+        ("LD", [Operand::DelayTimer, Operand::Register(x)]) => Instruction::SetDelay(*x),
+        ("LD", [Operand::SoundTimer, Operand::Register(x)]) => Instruction::SetSound(*x),
+        ("LD", [Operand::Font, Operand::Register(x)]) => Instruction::LdFont(*x),
+        ("LD", [Operand::BigFont, Operand::Register(x)]) => Instruction::LdBigFont(*x),
+        ("LD", [Operand::Bcd, Operand::Register(x)]) => Instruction::LdBcd(*x),
+        ("LD", [Operand::Audio, Operand::IndirectI]) => Instruction::LdAudio,
+        ("LD", [Operand::Pitch, Operand::Register(x)]) => Instruction::LdPitch(*x),
+        ("LD", [Operand::Flags, Operand::Register(x)]) => Instruction::StoreFlags(*x),
+        ("LD", [Operand::Register(x), Operand::Flags]) => Instruction::LoadFlags(*x),
+        ("LD", [Operand::IndirectI, Operand::Register(x)]) => Instruction::StoreRegs(*x),
+        ("LD", [Operand::Register(x), Operand::IndirectI]) => Instruction::LoadRegs(*x),
+        ("ADD", [Operand::Register(x), Operand::Register(y)]) => Instruction::AddReg(*x, *y),
+        ("ADD", [Operand::Register(x), Operand::Value(nn)]) => Instruction::Add(*x, symbols.byte(nn, line)?),
+        ("ADD", [Operand::I, Operand::Register(x)]) => Instruction::AddI(*x),
+        ("OR", [Operand::Register(x), Operand::Register(y)]) => Instruction::Or(*x, *y),
+        ("AND", [Operand::Register(x), Operand::Register(y)]) => Instruction::And(*x, *y),
+        ("XOR", [Operand::Register(x), Operand::Register(y)]) => Instruction::Xor(*x, *y),
+        ("SUB", [Operand::Register(x), Operand::Register(y)]) => Instruction::Sub(*x, *y),
+        ("SUBN", [Operand::Register(x), Operand::Register(y)]) => Instruction::Subn(*x, *y),
+        ("SHR", [Operand::Register(x)]) => Instruction::Shr(*x, *x),
+        ("SHR", [Operand::Register(x), Operand::Register(y)]) => Instruction::Shr(*x, *y),
+        ("SHL", [Operand::Register(x)]) => Instruction::Shl(*x, *x),
+        ("SHL", [Operand::Register(x), Operand::Register(y)]) => Instruction::Shl(*x, *y),
+        ("RND", [Operand::Register(x), Operand::Value(nn)]) => Instruction::Rnd(*x, symbols.byte(nn, line)?),
+        ("DRW", [Operand::Register(x), Operand::Register(y), Operand::Value(n)]) => {
+            Instruction::Drw(*x, *y, symbols.value(n, line, 0xF)? as u8)
+        },
+        ("SKP", [Operand::Register(x)]) => Instruction::Skp(*x),
+        ("SKNP", [Operand::Register(x)]) => Instruction::Sknp(*x),
+        // only planes 0 - 3 exist, but FN01 decodes any N and the disassembly has to assemble back
+        ("PLANE", [Operand::Value(n)]) => Instruction::Plane(symbols.value(n, line, 0xF)? as u8),
+        (mnemonic, _) if is_instruction(mnemonic) => return invalid(),
+        (mnemonic, _) => return error(line, format!("UNKNOWN INSTRUCTION '{}'", mnemonic)),
+    };
+    Ok(instruction)
+}
+
+fn is_instruction(mnemonic: &str) -> bool {
+    matches!(mnemonic, "SCD" | "SCU" | "CLS" | "RET" | "SCR" | "SCL" | "EXIT" | "LOW" | "HIGH" | "JP" | "CALL" | "SE" | "SNE" | "LD" | "ADD" | "OR" | "AND" |
+                       "XOR" | "SUB" | "SUBN" | "SHR" | "SHL" | "RND" | "DRW" | "SKP" | "SKNP" |
+                       "SAVE" | "LOAD" | "PLANE")
+}
+
+fn split_operands(text: &str) -> Vec<&str> {
+    if text.trim().is_empty() {
+        return Vec::new();
+    }
+    text.split(',').map(str::trim).collect()
+}
+
+// assembles Cortex/Chipper style source into a ROM that is loaded at 0x200
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    let mut symbols = Symbols { labels: HashMap::new(), defines: HashMap::new() };
+    let mut statements = Vec::new();
+    let mut address = PROGRAM_START;
+
+    // first pass: find every label, define and the size of every statement
+    for (index, raw_line) in source.lines().enumerate() {
+        let line = index + 1;
+        let mut text = raw_line.split(';').next().unwrap_or("").trim();
+
+        while let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if !is_identifier(label) {
+                break;
+            }
+            if symbols.labels.insert(label, address).is_some() {
+                return error(line, format!("LABEL '{}' IS DEFINED TWICE", label));
+            }
+            text = text[colon + 1..].trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let (first, rest) = match text.find(char::is_whitespace) {
+            Some(split) => (&text[..split], text[split..].trim()),
+            None => (text, ""),
+        };
+
+        // `define NAME value` and `NAME EQU value`
+        let define = if first.eq_ignore_ascii_case("define") {
+            rest.split_once(char::is_whitespace)
+        } else {
+            rest.split_once(char::is_whitespace)
+                .filter(|(equ, _)| equ.eq_ignore_ascii_case("equ"))
+                .map(|(_, value)| (first, value))
+        };
+        if let Some((name, value)) = define {
+            let name = name.trim();
+            if !is_identifier(name) {
+                return error(line, format!("NON VALID NAME '{}' FOR A DEFINE", name));
+            }
+            symbols.defines.insert(name, value.trim());
+            continue;
+        }
+
+        let mnemonic = first.to_ascii_uppercase();
+        let operands = split_operands(rest);
+        let size = match mnemonic.as_str() {
+            "DB" => operands.len(),
+            "DW" => operands.len() * 2,
+            _ if operands.iter().any(|operand| matches!(parse_operand(operand), Operand::Long(_))) => 4,
+            _ => 2,
+        };
+        statements.push(Statement { line, address, mnemonic, operands });
+        address += size;
+        if address > XO_MEMORY_SIZE {
+            return error(line, "PROGRAM DOES NOT FIT IN MEMORY".to_string());
+        }
+    }
+
+    // second pass: every symbol is known now
+    let mut rom = Vec::with_capacity(address - PROGRAM_START);
+    for statement in &statements {
+        debug_assert_eq!(statement.address, PROGRAM_START + rom.len());
+        match statement.mnemonic.as_str() {
+            "DB" => {
+                for operand in &statement.operands {
+                    rom.push(symbols.byte(operand, statement.line)?);
+                }
+            },
+            "DW" => {
+                for operand in &statement.operands {
+                    let word = symbols.evaluate(operand, statement.line, 0)?;
+                    if !(-0x8000..=0xFFFF).contains(&word) {
+                        return error(statement.line, format!("VALUE {} OF '{}' DOES NOT FIT IN A WORD", word, operand));
+                    }
+                    rom.extend_from_slice(&(word as u16).to_be_bytes());
+                }
+            },
+            _ => {
+                let instruction = encode_statement(statement, &symbols)?;
+                rom.extend_from_slice(&instruction.to_bytes());
+            },
+        }
+    }
+    Ok(rom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::disassemble;
+    use crate::instruction::Instruction;
+
+    // one of every instruction, each jump out of the straight line sits behind a skip,
+    // so the disassembler reaches all of them as code
+    const EVERY_INSTRUCTION: [Instruction; 52] = [
+        Instruction::ScrollDown(3),
+        Instruction::ScrollUp(4),
+        Instruction::Cls,
+        Instruction::ScrollRight,
+        Instruction::ScrollLeft,
+        Instruction::LowRes,
+        Instruction::HighRes,
+        Instruction::Call(0x200),
+        Instruction::StoreRange(1, 5),
+        Instruction::LoadRange(6, 2),
+        Instruction::Ld(0xA, 0x7F),
+        Instruction::Add(0xB, 0x01),
+        Instruction::LdReg(1, 2),
+        Instruction::Or(3, 4),
+        Instruction::And(5, 6),
+        Instruction::Xor(7, 8),
+        Instruction::AddReg(9, 0xA),
+        Instruction::Sub(0xB, 0xC),
+        Instruction::Shr(0xD, 0xE),
+        Instruction::Subn(0xF, 0),
+        Instruction::Shl(1, 1),
+        Instruction::LdI(0x200),
+        Instruction::Rnd(2, 0x0F),
+        Instruction::Drw(3, 4, 5),
+        Instruction::LdILong(0x1234),
+        Instruction::Plane(7),
+        Instruction::LdAudio,
+        Instruction::LdDelay(5),
+        Instruction::LdKey(6),
+        Instruction::SetDelay(7),
+        Instruction::SetSound(8),
+        Instruction::AddI(9),
+        Instruction::LdFont(0xA),
+        Instruction::LdBigFont(0xB),
+        Instruction::LdBcd(0xC),
+        Instruction::LdPitch(0xD),
+        Instruction::StoreRegs(0xE),
+        Instruction::LoadRegs(0xF),
+        Instruction::StoreFlags(3),
+        Instruction::LoadFlags(4),
+        Instruction::Skp(1),
+        Instruction::Ret,
+        Instruction::Sknp(2),
+        Instruction::Exit,
+        Instruction::SeByte(3, 0x42),
+        Instruction::JpV0(0x200),
+        Instruction::SneByte(4, 0x24),
+        Instruction::Ret,
+        Instruction::SeReg(5, 6),
+        Instruction::Ret,
+        Instruction::SneReg(7, 8),
+        Instruction::Jp(0x200),
+    ];
+
+    fn source(instructions: &[Instruction]) -> String {
+        instructions.iter().map(|instruction| format!("{}\n", instruction.mnemonic(|address| format!("{:#05X}", address)))).collect()
+    }
+
+    #[test]
+    fn assembles_every_instruction() {
+        let expected: Vec<u8> = EVERY_INSTRUCTION.iter().flat_map(Instruction::to_bytes).collect();
+        assert_eq!(assemble(&source(&EVERY_INSTRUCTION)).unwrap(), expected);
+    }
+
+    #[test]
+    fn disassembly_assembles_back_into_the_same_rom() {
+        let rom = assemble(&source(&EVERY_INSTRUCTION)).unwrap();
+        let listing = disassemble(&rom);
+        assert!(!listing.contains("DB "), "{}", listing);
+        assert_eq!(assemble(&listing).unwrap(), rom);
+    }
+
+    #[test]
+    fn data_assembles_back_into_the_same_rom() {
+        // everything after the jump is data, including bytes that don't decode
+        let rom = [0x12, 0x00, 0xFF, 0xFF, 0x00, 0x01, 0xF7, 0x01];
+        assert_eq!(assemble(&disassemble(&rom)).unwrap(), rom);
+    }
+}
@@ -0,0 +1,183 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+use crate::chip8::Chip8;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+// the pitch register value that plays the pattern at 4000 bits per second
+pub const DEFAULT_PITCH: u8 = 64;
+
+pub const DEFAULT_BEEP_FREQUENCY: f64 = 440.0;
+pub const DEFAULT_VOLUME: u8 = 25; // percent
+
+// how many bits of the pattern are played per second, XO-CHIP doubles it every 48 steps of pitch
+pub fn playback_rate(pitch: u8) -> f64 {
+    4000.0 * 2f64.powf((f64::from(pitch) - 64.0) / 48.0)
+}
+
+// the tone played while the sound timer runs, unless an XO-CHIP program loaded its own pattern
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Beeper {
+    pub frequency: f64,
+    pub volume: u8, // percent
+}
+
+impl Default for Beeper {
+    fn default() -> Self {
+        Beeper { frequency: DEFAULT_BEEP_FREQUENCY, volume: DEFAULT_VOLUME }
+    }
+}
+
+// turns the sound state into 16-bit mono PCM while the sound timer runs: a square wave,
+// or the XO-CHIP audio pattern played one bit at a time
+pub struct AudioRenderer {
+    sample_rate: u32,
+    beeper: Beeper,
+    position: f64, // in periods of the square wave, or in bits of the pattern
+}
+
+impl AudioRenderer {
+    pub fn new(sample_rate: u32, beeper: Beeper) -> AudioRenderer {
+        AudioRenderer { sample_rate, beeper, position: 0.0 }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // appends `count` samples, silence when the sound timer is 0
+    pub fn render(&mut self, chip8: &Chip8, count: usize, samples: &mut Vec<i16>) {
+        let playing = chip8.sound_timer() > 0;
+        if !playing {
+            samples.resize(samples.len() + count, 0);
+            return;
+        }
+        let amplitude = (i32::from(i16::MAX) * i32::from(self.beeper.volume.min(100)) / 100) as i16;
+        let sample_rate = f64::from(self.sample_rate);
+        match chip8.audio_pattern() {
+            Some(pattern) => {
+                let step = playback_rate(chip8.pitch()) / sample_rate;
+                let bits = (AUDIO_PATTERN_SIZE * 8) as f64;
+                for _ in 0..count {
+                    let bit = self.position as usize % (AUDIO_PATTERN_SIZE * 8);
+                    let value = (pattern[bit / 8] >> (7 - bit % 8)) & 1;
+                    samples.push(if value == 1 { amplitude } else { -amplitude });
+                    self.position = (self.position + step) % bits;
+                }
+            },
+            None => {
+                let step = self.beeper.frequency / sample_rate;
+                for _ in 0..count {
+                    samples.push(if self.position < 0.5 { amplitude } else { -amplitude });
+                    self.position = (self.position + step) % 1.0;
+                }
+            },
+        }
+    }
+}
+
+impl Default for AudioRenderer {
+    fn default() -> Self {
+        Self::new(DEFAULT_SAMPLE_RATE, Beeper::default())
+    }
+}
+
+// writes 16-bit mono PCM samples to a WAV file, the sizes in the header are filled in by finish()
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    data_size: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<WavWriter<W>> {
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?; // size of the fmt chunk
+        writer.write_all(&1u16.to_le_bytes())?; // PCM
+        writer.write_all(&1u16.to_le_bytes())?; // mono
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * 2).to_le_bytes())?; // bytes per second
+        writer.write_all(&2u16.to_le_bytes())?; // bytes per sample
+        writer.write_all(&16u16.to_le_bytes())?; // bits per sample
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        Ok(WavWriter { writer, data_size: 0 })
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        let bytes: Vec<u8> = samples.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+        self.writer.write_all(&bytes)?;
+        self.data_size += bytes.len() as u32;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(36 + self.data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&self.data_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::timers::TIMER_FREQUENCY;
+
+    const SAMPLES_PER_FRAME: usize = (DEFAULT_SAMPLE_RATE / TIMER_FREQUENCY) as usize;
+
+    // LD V0, 3 and LD ST, V0: a beep of three frames
+    fn beeping_chip8() -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.load_program(&[0x60, 0x03, 0xF0, 0x18]).unwrap();
+        chip8.step().unwrap();
+        chip8.step().unwrap();
+        chip8
+    }
+
+    // renders `frames` frames into a WAV file, counting the timers down like the frontend does
+    fn record(chip8: &mut Chip8, frames: usize) -> Vec<u8> {
+        let mut renderer = AudioRenderer::default();
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), renderer.sample_rate()).unwrap();
+        let mut samples = Vec::new();
+        for _ in 0..frames {
+            samples.clear();
+            renderer.render(chip8, SAMPLES_PER_FRAME, &mut samples);
+            wav.write_samples(&samples).unwrap();
+            chip8.tick_timers();
+        }
+        wav.finish().unwrap().into_inner()
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn wav_header_matches_the_recorded_frames() {
+        let wav = record(&mut beeping_chip8(), 5);
+        let data_size = 5 * SAMPLES_PER_FRAME * 2;
+        assert_eq!(wav.len(), 44 + data_size);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(u32_at(&wav, 4) as usize, 36 + data_size);
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&wav, 24), DEFAULT_SAMPLE_RATE);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(u32_at(&wav, 40) as usize, data_size);
+    }
+
+    #[test]
+    fn beeps_only_while_the_sound_timer_runs() {
+        let wav = record(&mut beeping_chip8(), 5);
+        let samples: Vec<i16> = wav[44..].chunks(2).map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]])).collect();
+        let (beep, silence) = samples.split_at(3 * SAMPLES_PER_FRAME);
+        assert!(beep.iter().all(|sample| *sample != 0));
+        assert!(silence.iter().all(|sample| *sample == 0));
+    }
+}
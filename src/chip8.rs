@@ -0,0 +1,1078 @@
+use std::fs::{self, File};
+use std::io::{self, Read, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::opcodes::{
+                    add_instruction, sub_instruction,
+                    shl_instruction, shr_instruction,
+                    jump_instruction, call_instruction,
+                    jump_offset_instruction, rand_instruction,
+                    add_no_overflow_instruction, Stack,
+                    Chip8EmulatorError, Chip8ErrorKind,
+                    Random, STACK_CAPACITY,
+                    };
+use crate::audio::{AUDIO_PATTERN_SIZE, DEFAULT_PITCH};
+use crate::instruction::{decode_at, length_at, DecodeError, Instruction};
+use crate::keypad::Keypad;
+use crate::quirks::{Platform, Quirks};
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+use crate::screen::{Screen, ALL_PLANES, SCREEN_WIDTH, SCREEN_HEIGHT, HIGH_RES_WIDTH, HIGH_RES_HEIGHT};
+use crate::timers::{DelayTimer, SoundTimer, Timer};
+
+pub const MEMORY_SIZE: usize = 4096;
+pub const XO_MEMORY_SIZE: usize = 0x10000;
+pub const PROGRAM_START: usize = 0x200;
+pub const FONT_START: usize = 0x50;
+pub const BIG_FONT_START: usize = FONT_START + FONT.len();
+pub const RPL_FLAGS: usize = 16;
+
+// FX0A halts the CPU until a key is pressed and released again, like on the COSMAC VIP,
+// the timers and the display keep running meanwhile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyWait {
+    Press(usize), // the register that receives the key
+    Release(usize, u8),
+}
+
+// a memory access made through I by the last executed instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryAccess {
+    Read(usize),
+    Write(usize),
+}
+
+const FONT: [u8; 80] = [0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+                        0x20, 0x60, 0x20, 0x20, 0x70, // 1
+                        0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+                        0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+                        0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+                        0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+                        0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+                        0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+                        0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+                        0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+                        0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+                        0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+                        0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+                        0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+                        0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+                        0xF0, 0x80, 0xF0, 0x80, 0x80]; // F
+
+// SUPER-CHIP 8x10 digits, A - F come from XO-CHIP
+const BIG_FONT: [u8; 160] = [0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+                             0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+                             0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+                             0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+                             0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+                             0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+                             0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+                             0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+                             0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+                             0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+                             0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+                             0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+                             0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+                             0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+                             0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+                             0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0]; // F
+
+pub struct Chip8 {
+    registers: [u8; 16], // registers v0 - vf
+    register_i: u16,
+    stack: Stack<u16>, // stack of addresses
+    memory: Vec<u8>, // 4 KB, or 64 KB on XO-CHIP
+    program_counter: usize,
+    screen: Screen,
+    planes: u8, // the bitplanes selected by FN01
+    audio_pattern: Option<[u8; AUDIO_PATTERN_SIZE]>, // set by F002, the beeper plays until then
+    pitch: u8,
+    keypad: Keypad,
+    key_wait: Option<KeyWait>,
+    delay_timer: DelayTimer,
+    sound_timer: SoundTimer,
+    memory_accesses: Vec<MemoryAccess>,
+    memory_writes: Vec<usize>, // since the last take_memory_writes()
+    memory_rewritten: bool, // memory may have changed anywhere, e.g. after loading a ROM
+    random: Random,
+    quirks: Quirks,
+    platform: Platform,
+    rpl_flags: [u8; RPL_FLAGS],
+    flags_path: Option<PathBuf>,
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chip8 {
+    pub fn new() -> Chip8 {
+        let mut memory = vec![0; MEMORY_SIZE];
+
+        // setting the font, and pointing I to it
+        memory[FONT_START..FONT_START + FONT.len()].copy_from_slice(&FONT);
+        memory[BIG_FONT_START..BIG_FONT_START + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
+
+        Chip8 {
+            registers: [0u8; 16],
+            register_i: FONT_START as u16,
+            stack: Stack::new(),
+            memory,
+            program_counter: PROGRAM_START,
+            screen: Screen::new(), // set the screen pixels to all off
+            planes: 1,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            keypad: Keypad::new(),
+            key_wait: None,
+            delay_timer: DelayTimer::new(),
+            sound_timer: SoundTimer::new(),
+            memory_accesses: Vec::new(),
+            memory_writes: Vec::new(),
+            memory_rewritten: true,
+            random: Random::new(),
+            quirks: Quirks::default(),
+            platform: Platform::default(),
+            rpl_flags: [0; RPL_FLAGS],
+            flags_path: None,
+        }
+    }
+
+    pub fn load_rom<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.memory_rewritten = true;
+        load_file_to_memory(&mut self.memory, path, PROGRAM_START).map(|_| ())
+    }
+
+    pub fn load_program(&mut self, program: &[u8]) -> io::Result<()> {
+        self.memory_rewritten = true;
+        copy_to_memory(&mut self.memory, program, PROGRAM_START)
+    }
+
+    // executes a single instruction, returns Ok(false) once the program has ended
+    pub fn step(&mut self) -> Result<bool, Chip8EmulatorError> {
+        if let Some(key_wait) = self.key_wait {
+            self.memory_accesses.clear();
+            self.wait_for_key(key_wait);
+            return Ok(true);
+        }
+        let address = self.program_counter;
+        let out_of_bounds = Chip8EmulatorError {
+            program_counter: address,
+            opcode: None,
+            kind: Chip8ErrorKind::ProgramCounterOutOfBounds,
+        };
+        let current_instruction = self.fetch_instruction().ok_or(out_of_bounds)?;
+        if current_instruction == 0xFFFF {
+            return Ok(false);
+        }
+        // 00FD ends a SUPER-CHIP program the same way
+        if current_instruction == 0x00FD && self.platform >= Platform::SuperChip {
+            return Ok(false);
+        }
+
+        let decoded = decode_at(&self.memory, address).ok_or(out_of_bounds)?;
+
+        self.memory_accesses.clear();
+        let result = decoded
+            .map_err(Chip8ErrorKind::InvalidInstruction)
+            .and_then(|instruction| {
+                // point to the next instruction before executing, so jumps and calls land where they should
+                self.program_counter += instruction.length();
+                self.execute_instruction(instruction)
+            });
+        match result {
+            Ok(()) => Ok(true),
+            Err(kind) => {
+                // leave the pc on the faulting instruction so the host can inspect it
+                self.program_counter = address;
+                Err(Chip8EmulatorError {
+                    program_counter: address,
+                    opcode: Some(current_instruction),
+                    kind,
+                })
+            },
+        }
+    }
+
+    // a halted step only looks at the keypad
+    fn wait_for_key(&mut self, key_wait: KeyWait) {
+        match key_wait {
+            KeyWait::Press(x) => {
+                if let Some(key) = self.keypad.pressed_key() {
+                    self.key_wait = Some(KeyWait::Release(x, key));
+                }
+            },
+            KeyWait::Release(x, key) => {
+                if !self.keypad.is_pressed(key.into()) {
+                    self.registers[x] = key;
+                    self.key_wait = None;
+                }
+            },
+        }
+    }
+
+    pub fn fetch_instruction(&self) -> Option<u16> {
+        let high = *self.memory.get(self.program_counter)?;
+        let low = *self.memory.get(self.program_counter + 1)?;
+        Some((u16::from(high) << 8) | u16::from(low))
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    pub fn set_register(&mut self, index: usize, value: u8) {
+        self.registers[index] = value;
+    }
+
+    pub fn register_i(&self) -> u16 {
+        self.register_i
+    }
+
+    pub fn set_register_i(&mut self, value: u16) {
+        self.register_i = value;
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        self.memory_rewritten = true;
+        &mut self.memory
+    }
+
+    pub fn program_counter(&self) -> usize {
+        self.program_counter
+    }
+
+    pub fn set_program_counter(&mut self, address: usize) {
+        self.program_counter = address;
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    pub fn keypad(&self) -> &Keypad {
+        &self.keypad
+    }
+
+    pub fn keypad_mut(&mut self) -> &mut Keypad {
+        &mut self.keypad
+    }
+
+    // set while FX0A waits for a key
+    pub fn key_wait(&self) -> Option<KeyWait> {
+        self.key_wait
+    }
+
+    pub fn memory_accesses(&self) -> &[MemoryAccess] {
+        &self.memory_accesses
+    }
+
+    // the addresses written since the last call, across any number of steps, or None when
+    // memory may have changed anywhere, so a history only has to look at those bytes
+    pub fn take_memory_writes(&mut self) -> Option<Vec<usize>> {
+        let writes = std::mem::take(&mut self.memory_writes);
+        if std::mem::replace(&mut self.memory_rewritten, false) {
+            return None;
+        }
+        Some(writes)
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    // XO-CHIP programs get 64 KB of memory, set the platform before loading a ROM
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+        self.memory.resize(platform.memory_size(), 0);
+        self.memory_rewritten = true;
+    }
+
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    pub fn audio_pattern(&self) -> Option<&[u8; AUDIO_PATTERN_SIZE]> {
+        self.audio_pattern.as_ref()
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub fn rpl_flags(&self) -> &[u8; RPL_FLAGS] {
+        &self.rpl_flags
+    }
+
+    // loads the RPL flags saved by an earlier run, FX75 writes them back to the same file
+    pub fn set_flags_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        match fs::read(&path) {
+            Ok(flags) => {
+                let length = flags.len().min(RPL_FLAGS);
+                self.rpl_flags[..length].copy_from_slice(&flags[..length]);
+            },
+            Err(error) if error.kind() == ErrorKind::NotFound => {},
+            Err(error) => return Err(error),
+        }
+        self.flags_path = Some(path.as_ref().to_path_buf());
+        Ok(())
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    // makes CXNN repeatable, e.g. for tests or bug reports
+    pub fn seed_random(&mut self, seed: u64) {
+        self.random = Random::from_seed(seed);
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer.get_timer()
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer.get_timer()
+    }
+
+    // counts both timers down by one 60 Hz frame, the host calls it as emulated time passes
+    pub fn tick_timers(&mut self) {
+        self.delay_timer.tick();
+        self.sound_timer.tick();
+    }
+
+    // serializes the whole machine into a versioned save state
+    pub fn save_state(&self) -> Vec<u8> {
+        self.write_state(true)
+    }
+
+    // a save state of everything but memory, for histories that follow memory through take_memory_writes()
+    pub fn save_state_without_memory(&self) -> Vec<u8> {
+        self.write_state(false)
+    }
+
+    // restores a save state, the machine is left untouched if the state is not valid
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), SaveStateError> {
+        self.read_state(state, true)
+    }
+
+    // restores a state from save_state_without_memory(), memory is left as it is
+    pub fn load_state_without_memory(&mut self, state: &[u8]) -> Result<(), SaveStateError> {
+        self.read_state(state, false)
+    }
+
+    fn write_state(&self, with_memory: bool) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        writer.write_u8(self.platform.to_byte());
+        writer.write_u8(self.quirks.to_bits());
+        writer.write_bytes(&self.registers);
+        writer.write_u16(self.register_i);
+        writer.write_u16(self.program_counter as u16);
+        writer.write_u8(self.stack.len() as u8);
+        for address in &self.stack {
+            writer.write_u16(*address);
+        }
+        writer.write_u32(self.memory.len() as u32);
+        if with_memory {
+            writer.write_bytes(&self.memory);
+        }
+
+        writer.write_u16(self.screen.width() as u16);
+        writer.write_u16(self.screen.height() as u16);
+        for y in 0..self.screen.height() {
+            for x in 0..self.screen.width() {
+                writer.write_u8(self.screen.get_color(&(x as u8), &(y as u8)).expect("COORDINATES OUT OF BOUND"));
+            }
+        }
+        writer.write_u8(self.planes);
+        writer.write_u8(u8::from(self.audio_pattern.is_some()));
+        writer.write_bytes(&self.audio_pattern.unwrap_or_default());
+        writer.write_u8(self.pitch);
+
+        let keys = (0..16).fold(0u16, |keys, key| keys | (u16::from(self.keypad.is_pressed(key)) << key));
+        writer.write_u16(keys);
+        let (wait_kind, wait_register, wait_key) = match self.key_wait {
+            None => (0, 0, 0),
+            Some(KeyWait::Press(x)) => (1, x as u8, 0),
+            Some(KeyWait::Release(x, key)) => (2, x as u8, key),
+        };
+        writer.write_u8(wait_kind);
+        writer.write_u8(wait_register);
+        writer.write_u8(wait_key);
+        writer.write_u8(self.delay_timer.get_timer());
+        writer.write_u8(self.sound_timer.get_timer());
+        writer.write_u64(self.random.state());
+        writer.finish()
+    }
+
+    fn read_state(&mut self, state: &[u8], with_memory: bool) -> Result<(), SaveStateError> {
+        let mut reader = StateReader::new(state)?;
+        let platform = Platform::from_byte(reader.read_u8()?).ok_or(SaveStateError::Invalid("PLATFORM"))?;
+        let quirks = Quirks::from_bits(reader.read_u8()?).ok_or(SaveStateError::Invalid("QUIRKS"))?;
+        let mut registers = [0u8; 16];
+        registers.copy_from_slice(reader.read_bytes(16)?);
+        let register_i = reader.read_u16()?;
+        let program_counter = reader.read_u16()? as usize;
+        let stack_length = reader.read_u8()? as usize;
+        if stack_length > STACK_CAPACITY {
+            return Err(SaveStateError::Invalid("STACK"));
+        }
+        let mut stack = Stack::new();
+        for _ in 0..stack_length {
+            stack.push(reader.read_u16()?);
+        }
+        let memory_size = reader.read_u32()? as usize;
+        if memory_size != platform.memory_size() || (!with_memory && memory_size != self.memory.len()) {
+            return Err(SaveStateError::Invalid("MEMORY SIZE"));
+        }
+        if program_counter >= memory_size {
+            return Err(SaveStateError::Invalid("PROGRAM COUNTER"));
+        }
+        let memory = if with_memory { Some(reader.read_bytes(memory_size)?.to_vec()) } else { None };
+
+        let (width, height) = (reader.read_u16()? as usize, reader.read_u16()? as usize);
+        if (width, height) != (SCREEN_WIDTH, SCREEN_HEIGHT) && (width, height) != (HIGH_RES_WIDTH, HIGH_RES_HEIGHT) {
+            return Err(SaveStateError::Invalid("SCREEN SIZE"));
+        }
+        let mut screen = Screen::with_size(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let color = reader.read_u8()?;
+                if color > ALL_PLANES {
+                    return Err(SaveStateError::Invalid("PIXEL"));
+                }
+                screen.set_color(&(x as u8), &(y as u8), color);
+            }
+        }
+        let planes = reader.read_u8()?;
+        if planes > ALL_PLANES {
+            return Err(SaveStateError::Invalid("PLANES"));
+        }
+        let has_audio_pattern = match reader.read_u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(SaveStateError::Invalid("AUDIO PATTERN")),
+        };
+        let mut audio_pattern = [0u8; AUDIO_PATTERN_SIZE];
+        audio_pattern.copy_from_slice(reader.read_bytes(AUDIO_PATTERN_SIZE)?);
+        let audio_pattern = if has_audio_pattern { Some(audio_pattern) } else { None };
+        let pitch = reader.read_u8()?;
+
+        let keys = reader.read_u16()?;
+        let (wait_kind, wait_register, wait_key) = (reader.read_u8()?, reader.read_u8()? as usize, reader.read_u8()?);
+        if wait_register >= 16 || wait_key >= 16 {
+            return Err(SaveStateError::Invalid("KEY WAIT"));
+        }
+        let key_wait = match wait_kind {
+            0 => None,
+            1 => Some(KeyWait::Press(wait_register)),
+            2 => Some(KeyWait::Release(wait_register, wait_key)),
+            _ => return Err(SaveStateError::Invalid("KEY WAIT")),
+        };
+        let delay_timer = reader.read_u8()?;
+        let sound_timer = reader.read_u8()?;
+        let random = Random::from_seed(reader.read_u64()?);
+        reader.finish()?;
+
+        self.platform = platform;
+        self.quirks = quirks;
+        self.registers = registers;
+        self.register_i = register_i;
+        self.program_counter = program_counter;
+        self.stack = stack;
+        if let Some(memory) = memory {
+            self.memory = memory;
+            self.memory_rewritten = true;
+        }
+        self.screen = screen;
+        self.planes = planes;
+        self.audio_pattern = audio_pattern;
+        self.pitch = pitch;
+        for key in 0..16 {
+            if keys & (1 << key) != 0 {
+                self.keypad.press_key(key);
+            } else {
+                self.keypad.release_key(key);
+            }
+        }
+        self.key_wait = key_wait;
+        self.delay_timer.set_timer(delay_timer);
+        self.sound_timer.set_timer(sound_timer);
+        self.random = random;
+        self.memory_accesses.clear();
+        Ok(())
+    }
+
+    // skips over the next instruction, which on XO-CHIP is 4 bytes long if it is F000 NNNN
+    fn skip_instruction(&mut self) {
+        self.program_counter += match self.platform {
+            Platform::XoChip => length_at(&self.memory, self.program_counter),
+            _ => 2,
+        };
+    }
+
+    fn memory_index(&self, offset: usize) -> Result<usize, Chip8ErrorKind> {
+        let index = self.register_i as usize + offset;
+        if index < self.memory.len() {
+            Ok(index)
+        } else {
+            Err(Chip8ErrorKind::MemoryOutOfBounds(index))
+        }
+    }
+
+    // reads the byte at I + offset
+    fn read_memory(&mut self, offset: usize) -> Result<u8, Chip8ErrorKind> {
+        let index = self.memory_index(offset)?;
+        self.memory_accesses.push(MemoryAccess::Read(index));
+        Ok(self.memory[index])
+    }
+
+    // writes the byte at I + offset
+    fn write_memory(&mut self, offset: usize, value: u8) -> Result<(), Chip8ErrorKind> {
+        let index = self.memory_index(offset)?;
+        self.memory_accesses.push(MemoryAccess::Write(index));
+        // nobody may be taking the writes, so a long list gives way to rewriting everything
+        if self.memory_writes.len() < self.memory.len() {
+            self.memory_writes.push(index);
+        } else {
+            self.memory_rewritten = true;
+        }
+        self.memory[index] = value;
+        Ok(())
+    }
+
+    fn execute_instruction(&mut self, instruction: Instruction) -> Result<(), Chip8ErrorKind> {
+        if self.platform < required_platform(instruction) {
+            return Err(Chip8ErrorKind::InvalidInstruction(DecodeError { opcode: instruction.encode() }));
+        }
+        match instruction {
+            Instruction::ScrollDown(n) => {
+                self.screen.scroll_down(n.into(), self.planes);
+            },
+            Instruction::ScrollUp(n) => {
+                self.screen.scroll_up(n.into(), self.planes);
+            },
+            Instruction::Cls => {
+                self.screen.clear_planes(self.planes);
+            },
+            Instruction::Ret => {
+                let return_address = self.stack.pop().ok_or(Chip8ErrorKind::StackUnderflow)?;
+                self.program_counter = return_address as usize;
+            },
+            Instruction::ScrollRight => {
+                self.screen.scroll_right(4, self.planes);
+            },
+            Instruction::ScrollLeft => {
+                self.screen.scroll_left(4, self.planes);
+            },
+            Instruction::Exit => {
+                // step() stops before getting here
+                return Err(Chip8ErrorKind::InvalidInstruction(DecodeError { opcode: instruction.encode() }));
+            },
+            Instruction::LowRes => {
+                self.screen.resize(SCREEN_WIDTH, SCREEN_HEIGHT);
+            },
+            Instruction::HighRes => {
+                self.screen.resize(HIGH_RES_WIDTH, HIGH_RES_HEIGHT);
+            },
+            Instruction::Jp(nnn) => {
+                jump_instruction(&mut self.program_counter, nnn);
+            },
+            Instruction::Call(nnn) => {
+                call_instruction(&mut self.program_counter, nnn, &mut self.stack)?;
+            },
+            Instruction::SeByte(x, nn) => {
+                if self.registers[x] == nn {
+                    self.skip_instruction();
+                }
+            },
+            Instruction::SneByte(x, nn) => {
+                if self.registers[x] != nn {
+                    self.skip_instruction();
+                }
+            },
+            Instruction::SeReg(x, y) => {
+                if self.registers[x] == self.registers[y] {
+                    self.skip_instruction();
+                }
+            },
+            Instruction::StoreRange(x, y) => {
+                self.memory_index(x.abs_diff(y))?;
+                // the registers are stored in reverse when X is above Y
+                for (offset, register) in register_range(x, y).enumerate() {
+                    self.write_memory(offset, self.registers[register])?;
+                }
+            },
+            Instruction::LoadRange(x, y) => {
+                self.memory_index(x.abs_diff(y))?;
+                for (offset, register) in register_range(x, y).enumerate() {
+                    self.registers[register] = self.read_memory(offset)?;
+                }
+            },
+            Instruction::Ld(x, nn) => {
+                self.registers[x] = nn;
+            },
+            Instruction::Add(x, nn) => {
+                add_no_overflow_instruction(&mut self.registers, x, nn);
+            },
+            Instruction::LdReg(x, y) => {
+                self.registers[x] = self.registers[y];
+            },
+            Instruction::Or(x, y) => {
+                self.registers[x] |= self.registers[y];
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
+                }
+            },
+            Instruction::And(x, y) => {
+                self.registers[x] &= self.registers[y];
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
+                }
+            },
+            Instruction::Xor(x, y) => {
+                self.registers[x] ^= self.registers[y];
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
+                }
+            },
+            Instruction::AddReg(x, y) => {
+                let vy_value = self.registers[y];
+                add_instruction(&mut self.registers, x, vy_value);
+            },
+            Instruction::Sub(x, y) => {
+                let vy_value = self.registers[y];
+                sub_instruction(&mut self.registers, x, vy_value);
+            },
+            Instruction::Shr(x, y) => {
+                if self.quirks.shift_vy {
+                    self.registers[x] = self.registers[y];
+                }
+                shr_instruction(&mut self.registers, x);
+            },
+            Instruction::Subn(x, y) => {
+                let (result, is_borrow) = self.registers[y].overflowing_sub(self.registers[x]);
+                self.registers[x] = result;
+                self.registers[0xF] = (!is_borrow).into();
+            },
+            Instruction::Shl(x, y) => {
+                if self.quirks.shift_vy {
+                    self.registers[x] = self.registers[y];
+                }
+                shl_instruction(&mut self.registers, x);
+            },
+            Instruction::SneReg(x, y) => {
+                if self.registers[x] != self.registers[y] {
+                    self.skip_instruction();
+                }
+            },
+            Instruction::LdI(nnn) => {
+                self.register_i = nnn;
+            },
+            Instruction::JpV0(nnn) => {
+                let offset_register = if self.quirks.jump_vx { usize::from(nnn >> 8) } else { 0 };
+                jump_offset_instruction(self.registers[offset_register], &mut self.program_counter, nnn);
+            },
+            Instruction::Rnd(x, nn) => {
+                rand_instruction(&mut self.registers, x, nn, &mut self.random);
+            },
+            Instruction::Drw(x, y, n) => {
+                let screen_width = self.screen.width();
+                let screen_height = self.screen.height();
+
+                // the starting position always wraps, only the sprite itself can be clipped
+                let x_start = usize::from(self.registers[x]) % screen_width;
+                let y_start = usize::from(self.registers[y]) % screen_height;
+
+                // DXY0 draws a 16x16 sprite, two bytes per row
+                let (sprite_width, rows) = match n {
+                    0 if self.platform >= Platform::SuperChip => (16, 16),
+                    _ => (8, usize::from(n)),
+                };
+                let row_bytes = sprite_width / 8;
+                // every sprite byte has to be readable before a single pixel flips
+                let sprite_bytes = (self.planes & ALL_PLANES).count_ones() as usize * rows * row_bytes;
+                if sprite_bytes > 0 {
+                    self.memory_index(sprite_bytes - 1)?;
+                }
+                self.registers[0xF] = 0;
+
+                // every selected plane gets its own sprite, stored one after the other
+                let mut sprite_offset = 0;
+                for plane in [1, 2] {
+                    if self.planes & plane == 0 {
+                        continue;
+                    }
+                    for i in 0..rows {
+                        let mut sprite_row: u16 = 0;
+                        for byte in 0..row_bytes {
+                            sprite_row = (sprite_row << 8) | u16::from(self.read_memory(sprite_offset + i * row_bytes + byte)?);
+                        }
+
+                        for j in 0..sprite_width {
+                            let (mut x_coord, mut y_coord) = (x_start + j, y_start + i);
+                            if self.quirks.clip_sprites && (x_coord >= screen_width || y_coord >= screen_height) {
+                                continue;
+                            }
+                            x_coord %= screen_width;
+                            y_coord %= screen_height;
+                            let (x_coord, y_coord) = (x_coord as u8, y_coord as u8);
+
+                            if (sprite_row >> (sprite_width - 1 - j)) & 1 == 1 {
+                                let color = self.screen.get_color(&x_coord, &y_coord).expect("COORDINATES OUT OF BOUND");
+                                if color & plane != 0 {
+                                    self.registers[0xF] = 1;
+                                }
+                                self.screen.set_color(&x_coord, &y_coord, color ^ plane);
+                            }
+                        }
+                    }
+                    sprite_offset += rows * row_bytes;
+                }
+            },
+            Instruction::Skp(x) => {
+                if self.keypad.is_pressed(self.registers[x].into()) {
+                    self.skip_instruction();
+                }
+            },
+            Instruction::Sknp(x) => {
+                if !self.keypad.is_pressed(self.registers[x].into()) {
+                    self.skip_instruction();
+                }
+            },
+            Instruction::LdILong(nnnn) => {
+                self.register_i = nnnn;
+            },
+            Instruction::Plane(n) => {
+                self.planes = n & ALL_PLANES;
+            },
+            Instruction::LdAudio => {
+                self.memory_index(AUDIO_PATTERN_SIZE - 1)?;
+                let mut pattern = [0u8; AUDIO_PATTERN_SIZE];
+                for (i, byte) in pattern.iter_mut().enumerate() {
+                    *byte = self.read_memory(i)?;
+                }
+                self.audio_pattern = Some(pattern);
+            },
+            Instruction::LdPitch(x) => {
+                self.pitch = self.registers[x];
+            },
+            Instruction::LdDelay(x) => {
+                self.registers[x] = self.delay_timer.get_timer();
+            },
+            Instruction::LdKey(x) => {
+                self.key_wait = Some(KeyWait::Press(x));
+            },
+            Instruction::SetDelay(x) => {
+                self.delay_timer.set_timer(self.registers[x]);
+            },
+            Instruction::SetSound(x) => {
+                self.sound_timer.set_timer(self.registers[x]);
+            },
+            Instruction::AddI(x) => {
+                let (result, _overflow) = self.register_i.overflowing_add(self.registers[x].into());
+                self.register_i = result;
+            },
+            Instruction::LdFont(x) => {
+                // every font sprite is 5 bytes long, only the low nibble of vx selects a digit
+                self.register_i = (FONT_START + usize::from(self.registers[x] & 0xF) * 5) as u16;
+            },
+            Instruction::LdBigFont(x) => {
+                self.register_i = (BIG_FONT_START + usize::from(self.registers[x] & 0xF) * 10) as u16;
+            },
+            Instruction::LdBcd(x) => {
+                let value = self.registers[x];
+                // check the last byte first, so nothing is written when it doesn't fit
+                self.memory_index(2)?;
+                self.write_memory(0, value / 100)?;
+                self.write_memory(1, value / 10 % 10)?;
+                self.write_memory(2, value % 10)?;
+            },
+            Instruction::StoreRegs(x) => {
+                self.memory_index(x)?;
+                for i in 0..=x {
+                    self.write_memory(i, self.registers[i])?;
+                }
+                if self.quirks.increment_i {
+                    self.register_i = self.register_i.wrapping_add(x as u16 + 1);
+                }
+            },
+            Instruction::LoadRegs(x) => {
+                self.memory_index(x)?;
+                for i in 0..=x {
+                    self.registers[i] = self.read_memory(i)?;
+                }
+                if self.quirks.increment_i {
+                    self.register_i = self.register_i.wrapping_add(x as u16 + 1);
+                }
+            },
+            Instruction::StoreFlags(x) => {
+                self.rpl_flags[..=x].copy_from_slice(&self.registers[..=x]);
+                if let Some(path) = &self.flags_path {
+                    fs::write(path, self.rpl_flags).map_err(|_| Chip8ErrorKind::FlagsNotSaved)?;
+                }
+            },
+            Instruction::LoadFlags(x) => {
+                self.registers[..=x].copy_from_slice(&self.rpl_flags[..=x]);
+            },
+        }
+        Ok(())
+    }
+}
+
+fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    }
+}
+
+// the oldest platform that has the instruction
+fn required_platform(instruction: Instruction) -> Platform {
+    match instruction {
+        Instruction::ScrollDown(_) | Instruction::ScrollRight | Instruction::ScrollLeft | Instruction::Exit |
+        Instruction::LowRes | Instruction::HighRes | Instruction::LdBigFont(_) |
+        Instruction::StoreFlags(_) | Instruction::LoadFlags(_) => Platform::SuperChip,
+        Instruction::ScrollUp(_) | Instruction::StoreRange(..) | Instruction::LoadRange(..) |
+        Instruction::LdILong(_) | Instruction::Plane(_) | Instruction::LdAudio | Instruction::LdPitch(_) => Platform::XoChip,
+        _ => Platform::Chip8,
+    }
+}
+
+pub fn load_file_to_memory<P: AsRef<Path>>(memory: &mut [u8], file_path: P, start_address: usize) -> io::Result<usize> {
+    let mut file = File::open(file_path)?;
+    let mut rom = Vec::new();
+    file.read_to_end(&mut rom)?;
+
+    copy_to_memory(memory, &rom, start_address)?;
+    Ok(rom.len())
+}
+
+fn copy_to_memory(memory: &mut [u8], rom: &[u8], start_address: usize) -> io::Result<()> {
+    let destination = memory.get_mut(start_address..start_address + rom.len())
+                            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "ROM DOES NOT FIT IN MEMORY"))?;
+    destination.copy_from_slice(rom);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::savestate::VERSION;
+    use crate::screen::PixelState;
+
+    // a machine for `platform`, with its quirks and `program` loaded at 0x200
+    fn machine_for(platform: Platform, program: &[u16]) -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.set_platform(platform);
+        chip8.set_quirks(platform.quirks());
+        let bytes: Vec<u8> = program.iter().flat_map(|word| word.to_be_bytes()).collect();
+        chip8.load_program(&bytes).unwrap();
+        chip8
+    }
+
+    fn machine(program: &[u16]) -> Chip8 {
+        machine_for(Platform::Chip8, program)
+    }
+
+    fn run(chip8: &mut Chip8, steps: usize) {
+        for _ in 0..steps {
+            chip8.step().unwrap();
+        }
+    }
+
+    fn lit(chip8: &Chip8, x: u8, y: u8) -> bool {
+        chip8.screen().get_pixel(&x, &y) == Some(PixelState::On)
+    }
+
+    fn color(chip8: &Chip8, x: u8, y: u8) -> u8 {
+        chip8.screen().get_color(&x, &y).unwrap()
+    }
+
+    #[test]
+    fn stops_at_memory_accesses_past_the_end_before_changing_anything() {
+        // V0 - V2 would be stored at FFE - 1000
+        let mut chip8 = machine(&[0xAFFE, 0x6011, 0xF255]);
+        run(&mut chip8, 2);
+        let error = chip8.step().unwrap_err();
+        assert_eq!(error.kind, Chip8ErrorKind::MemoryOutOfBounds(0x1000));
+        assert_eq!((error.program_counter, error.opcode), (0x204, Some(0xF255)));
+        assert_eq!(chip8.program_counter(), 0x204);
+        assert_eq!(chip8.memory()[0xFFE..], [0, 0]);
+
+        let mut chip8 = machine(&[0xAFF8, 0x6011, 0xFF65]);
+        run(&mut chip8, 2);
+        assert_eq!(chip8.step().unwrap_err().kind, Chip8ErrorKind::MemoryOutOfBounds(0x1007));
+        assert_eq!(chip8.registers()[0], 0x11);
+    }
+
+    #[test]
+    fn stops_when_the_program_counter_leaves_memory() {
+        let mut chip8 = machine(&[0x1FFF]);
+        run(&mut chip8, 1);
+        assert_eq!(chip8.step().unwrap_err().kind, Chip8ErrorKind::ProgramCounterOutOfBounds);
+    }
+
+    #[test]
+    fn names_the_opcode_that_failed_to_decode() {
+        let mut chip8 = machine(&[0x5001]);
+        assert_eq!(chip8.step().unwrap_err().kind, Chip8ErrorKind::InvalidInstruction(DecodeError { opcode: 0x5001 }));
+    }
+
+    #[test]
+    fn refuses_save_states_it_cannot_read() {
+        let mut chip8 = machine(&[0x6042]);
+        let state = chip8.save_state();
+        run(&mut chip8, 1);
+
+        let mut magic = state.clone();
+        magic[0] = b'X';
+        assert!(matches!(chip8.load_state(&magic), Err(SaveStateError::NotASaveState)));
+        let mut version = state.clone();
+        version[4..6].copy_from_slice(&(VERSION + 1).to_be_bytes());
+        assert!(matches!(chip8.load_state(&version), Err(SaveStateError::UnsupportedVersion(v)) if v == VERSION + 1));
+        assert!(matches!(chip8.load_state(&state[..state.len() - 1]), Err(SaveStateError::Truncated)));
+        let mut longer = state.clone();
+        longer.push(0);
+        assert!(matches!(chip8.load_state(&longer), Err(SaveStateError::Invalid("LENGTH"))));
+
+        // none of them touched the machine
+        assert_eq!((chip8.registers()[0], chip8.program_counter()), (0x42, 0x202));
+        chip8.load_state(&state).unwrap();
+        assert_eq!((chip8.registers()[0], chip8.program_counter()), (0, 0x200));
+    }
+
+    // runs `program` to its end with the VIP's quirks, except `quirk` which is set to `enabled`
+    fn run_with_quirk(quirk: &str, enabled: bool, program: &[u16]) -> Chip8 {
+        let mut quirks = Quirks::cosmac_vip();
+        quirks.set(quirk, enabled).unwrap();
+        let mut chip8 = machine(program);
+        chip8.set_quirks(quirks);
+        run(&mut chip8, program.len());
+        chip8
+    }
+
+    #[test]
+    fn shift_quirk_shifts_vy_into_vx() {
+        let program = [0x6181, 0x6204, 0x8126];
+        let chip8 = run_with_quirk("shift", true, &program);
+        assert_eq!((chip8.registers()[1], chip8.registers()[0xF]), (0x02, 0));
+        let chip8 = run_with_quirk("shift", false, &program);
+        assert_eq!((chip8.registers()[1], chip8.registers()[0xF]), (0x40, 1));
+    }
+
+    #[test]
+    fn jump_quirk_adds_vx_instead_of_v0() {
+        let program = [0x6004, 0x6208, 0xB210];
+        assert_eq!(run_with_quirk("jump", false, &program).program_counter(), 0x214);
+        assert_eq!(run_with_quirk("jump", true, &program).program_counter(), 0x218);
+    }
+
+    #[test]
+    fn memory_quirk_moves_i_past_the_registers() {
+        let program = [0xA300, 0xF255];
+        assert_eq!(run_with_quirk("memory", true, &program).register_i(), 0x303);
+        assert_eq!(run_with_quirk("memory", false, &program).register_i(), 0x300);
+    }
+
+    #[test]
+    fn vf_reset_quirk_clears_vf_after_logic() {
+        let program = [0x6F05, 0x8011];
+        assert_eq!(run_with_quirk("vf-reset", true, &program).registers()[0xF], 0);
+        assert_eq!(run_with_quirk("vf-reset", false, &program).registers()[0xF], 5);
+    }
+
+    #[test]
+    fn clip_quirk_cuts_sprites_off_at_the_edge() {
+        // the 0 digit from the font, drawn from x = 62 so half of it is past the right edge
+        let program = [0x603E, 0xD015];
+        let chip8 = run_with_quirk("clip", true, &program);
+        assert!(lit(&chip8, 63, 0) && !lit(&chip8, 0, 0));
+        let chip8 = run_with_quirk("clip", false, &program);
+        assert!(lit(&chip8, 63, 0) && lit(&chip8, 0, 0) && lit(&chip8, 1, 0));
+    }
+
+    #[test]
+    fn scrolls_and_draws_16x16_sprites_in_high_resolution() {
+        // a solid 16x16 sprite right after the code, at 20C
+        let mut program = vec![0x00FF, 0xA20C, 0xD010, 0x00C1, 0x00FB, 0x00FC];
+        program.extend([0xFFFF; 16]);
+        let mut chip8 = machine_for(Platform::SuperChip, &program);
+        run(&mut chip8, 3);
+        assert!(chip8.screen().is_high_res());
+        assert!(lit(&chip8, 0, 0) && lit(&chip8, 15, 15) && !lit(&chip8, 16, 0) && !lit(&chip8, 0, 16));
+        run(&mut chip8, 1);
+        assert!(!lit(&chip8, 0, 0) && lit(&chip8, 0, 1) && lit(&chip8, 0, 16));
+        run(&mut chip8, 1);
+        assert!(!lit(&chip8, 3, 1) && lit(&chip8, 4, 1) && lit(&chip8, 19, 1) && !lit(&chip8, 20, 1));
+        run(&mut chip8, 1);
+        assert!(lit(&chip8, 0, 1) && lit(&chip8, 15, 1) && !lit(&chip8, 16, 1));
+    }
+
+    #[test]
+    fn refuses_super_chip_instructions_on_chip8() {
+        let mut chip8 = machine(&[0x00FB]);
+        assert_eq!(chip8.step().unwrap_err().kind, Chip8ErrorKind::InvalidInstruction(DecodeError { opcode: 0x00FB }));
+    }
+
+    #[test]
+    fn stores_and_loads_register_ranges_through_a_long_i() {
+        let mut chip8 = machine_for(Platform::XoChip, &[0xF000, 0x8000, 0x6011, 0x6122, 0x6233, 0x5022, 0x5203]);
+        run(&mut chip8, 6);
+        assert_eq!(chip8.register_i(), 0x8000);
+        assert_eq!(chip8.memory()[0x8000..0x8003], [0x11, 0x22, 0x33]);
+        // loaded in reverse, X is above Y
+        assert_eq!(chip8.registers()[..3], [0x33, 0x22, 0x11]);
+    }
+
+    #[test]
+    fn skips_over_f000_nnnn_as_a_single_instruction() {
+        let mut chip8 = machine_for(Platform::XoChip, &[0x3000, 0xF000, 0x1234, 0x6101]);
+        run(&mut chip8, 2);
+        assert_eq!((chip8.registers()[1], chip8.register_i()), (1, FONT_START as u16));
+    }
+
+    #[test]
+    fn draws_clears_and_scrolls_only_the_selected_planes() {
+        // one sprite row per plane right after the code, at 212
+        let program = [0xF301, 0x6101, 0xA212, 0xD011, 0xF201, 0x00E0, 0xF101, 0x00D1, 0x1210, 0x80C0];
+        let mut chip8 = machine_for(Platform::XoChip, &program);
+        run(&mut chip8, 4);
+        assert_eq!((color(&chip8, 0, 1), color(&chip8, 1, 1)), (3, 2));
+        run(&mut chip8, 2);
+        assert_eq!(chip8.planes(), 2);
+        assert_eq!((color(&chip8, 0, 1), color(&chip8, 1, 1)), (1, 0));
+        run(&mut chip8, 2);
+        assert_eq!((color(&chip8, 0, 0), color(&chip8, 0, 1)), (1, 0));
+    }
+
+    #[test]
+    fn waits_for_a_key_to_be_pressed_and_released() {
+        let mut chip8 = machine(&[0xF30A, 0x6101]);
+        run(&mut chip8, 2);
+        assert_eq!(chip8.key_wait(), Some(KeyWait::Press(3)));
+        chip8.keypad_mut().press_key(5);
+        run(&mut chip8, 2);
+        assert_eq!((chip8.key_wait(), chip8.registers()[3]), (Some(KeyWait::Release(3, 5)), 0));
+        chip8.keypad_mut().release_key(5);
+        run(&mut chip8, 1);
+        assert_eq!((chip8.key_wait(), chip8.registers()[3]), (None, 5));
+        assert_eq!(chip8.registers()[1], 0);
+        run(&mut chip8, 1);
+        assert_eq!(chip8.registers()[1], 1);
+    }
+}
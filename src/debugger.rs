@@ -0,0 +1,674 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::chip8::{Chip8, KeyWait, MemoryAccess};
+use crate::instruction::decode_at;
+use crate::palette::Palette;
+use crate::rewind::RewindBuffer;
+use crate::scheduler::Scheduler;
+use crate::screenshot::{self, DEFAULT_SCALE, MAX_SCALE};
+
+const HELP: &str = "commands:
+  step [count]          execute the next instruction(s)            (s)
+  continue              run until a breakpoint, an error or Ctrl-C (c)
+  reverse-step [count]  undo the last instruction(s)               (rs)
+  reverse-continue      run backwards to the previous breakpoint   (rc)
+  break <addr>          stop before executing the instruction      (b)
+  break if <cond>       stop once a condition becomes true, e.g. 'break if V5 == 0x3F'
+  break                 list breakpoints and conditions
+  delete [addr | #n]    remove a breakpoint, a condition, or everything (d)
+  watch <addr> [len]    stop after the memory is written
+  rwatch <addr> [len]   stop after the memory is read
+  awatch <addr> [len]   stop after the memory is read or written
+  watch                 list watchpoints
+  unwatch [addr]        remove the watchpoints covering an address, or all of them
+  regs                  show V0 - VF, I and the pc                 (r)
+  stack                 show the return addresses
+  mem <addr> [len]      dump memory                                (m)
+  disasm [addr] [count] disassemble memory, defaults to the pc     (x)
+  set <reg> <value>     set V0 - VF, I or PC
+  press <key>           hold down a key of the hex keypad, 0 - F
+  release <key>         let go of a key
+  screen                show the display
+  screenshot <file> [scale]  save the display to a .png, .ppm or .pbm file
+  savestate <file>      write the whole machine to a file
+  loadstate <file>      restore the machine from a file
+  help                  show this message                          (h)
+  quit                  exit the emulator                          (q)
+addresses and values are hex, 0x optional, counts are decimal unless 0x-prefixed
+an empty line repeats the last command";
+
+const DEFAULT_MEMORY_LENGTH: usize = 64;
+const DEFAULT_DISASM_COUNT: usize = 10;
+
+// why execution stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stop {
+    Running,
+    Breakpoint,
+    Watchpoint,
+    KeyWait,
+    Ended,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: usize,
+    pub length: usize,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    fn covers(&self, address: usize) -> bool {
+        address >= self.start && address - self.start < self.length
+    }
+
+    fn matches(&self, access: MemoryAccess) -> bool {
+        match (self.kind, access) {
+            (WatchKind::Read, MemoryAccess::Read(address)) |
+            (WatchKind::Write, MemoryAccess::Write(address)) |
+            (WatchKind::Access, MemoryAccess::Read(address) | MemoryAccess::Write(address)) => self.covers(address),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Register(usize),
+    I,
+    ProgramCounter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    target: Target,
+    comparison: Comparison,
+    value: usize,
+    text: String,
+    was_true: bool,
+}
+
+impl Condition {
+    // parses `<V0 - VF | I | PC> <== | != | < | <= | > | >=> <value>`
+    pub fn parse(words: &[&str]) -> Option<Condition> {
+        let [target, comparison, value] = words else {
+            return None;
+        };
+        let upper = target.to_ascii_uppercase();
+        let target = match upper.as_str() {
+            "I" => Target::I,
+            "PC" => Target::ProgramCounter,
+            _ => match upper.strip_prefix('V').and_then(|x| usize::from_str_radix(x, 16).ok()) {
+                Some(x) if x < 16 => Target::Register(x),
+                _ => return None,
+            },
+        };
+        let comparison = match *comparison {
+            "==" => Comparison::Eq,
+            "!=" => Comparison::Ne,
+            "<" => Comparison::Lt,
+            "<=" => Comparison::Le,
+            ">" => Comparison::Gt,
+            ">=" => Comparison::Ge,
+            _ => return None,
+        };
+        Some(Condition {
+            target,
+            comparison,
+            value: parse_hex(value)?,
+            text: words.join(" "),
+            was_true: false,
+        })
+    }
+
+    pub fn evaluate(&self, chip8: &Chip8) -> bool {
+        let current = match self.target {
+            Target::Register(x) => chip8.registers()[x] as usize,
+            Target::I => chip8.register_i() as usize,
+            Target::ProgramCounter => chip8.program_counter(),
+        };
+        match self.comparison {
+            Comparison::Eq => current == self.value,
+            Comparison::Ne => current != self.value,
+            Comparison::Lt => current < self.value,
+            Comparison::Le => current <= self.value,
+            Comparison::Gt => current > self.value,
+            Comparison::Ge => current >= self.value,
+        }
+    }
+}
+
+pub struct Debugger {
+    breakpoints: BTreeSet<usize>,
+    watchpoints: Vec<Watchpoint>,
+    conditions: Vec<Condition>,
+    scheduler: Scheduler,
+    last_command: String,
+    history: RewindBuffer,
+    palette: Palette,
+    screenshot_scale: usize,
+    interrupted: Arc<AtomicBool>,
+}
+
+// counts and lengths, decimal unless they start with 0x
+fn parse_number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+// addresses and register values, hex the way they are printed, the 0x is optional
+fn parse_hex(text: &str) -> Option<usize> {
+    let digits = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).unwrap_or(text);
+    usize::from_str_radix(digits, 16).ok()
+}
+
+impl Debugger {
+    // runs `continue` at the same speed as the frontend, and ticks the timers every `instructions_per_frame` instructions
+    pub fn new(instructions_per_frame: u32) -> Debugger {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            conditions: Vec::new(),
+            scheduler: Scheduler::new(instructions_per_frame),
+            last_command: String::new(),
+            history: RewindBuffer::default(),
+            palette: Palette::default(),
+            screenshot_scale: DEFAULT_SCALE,
+            interrupted: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // setting the flag stops a running `continue`, the host sets it from its Ctrl-C handler
+    pub fn interrupt_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.interrupted)
+    }
+
+    // the colours and the scale screenshots are taken with
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    pub fn set_screenshot_scale(&mut self, scale: usize) {
+        self.screenshot_scale = scale;
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<usize> {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, address: usize) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    // conditions only stop execution when they change from false to true
+    pub fn add_condition(&mut self, chip8: &Chip8, mut condition: Condition) {
+        condition.was_true = condition.evaluate(chip8);
+        self.conditions.push(condition);
+    }
+
+    // runs the command prompt until the user quits or the program ends
+    pub fn run<R: BufRead, W: Write>(&mut self, chip8: &mut Chip8, input: &mut R, output: &mut W) -> io::Result<()> {
+        self.show_location(chip8, output)?;
+        loop {
+            write!(output, "(chip8) ")?;
+            output.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let mut command = line.trim().to_string();
+            if command.is_empty() {
+                command = self.last_command.clone();
+            }
+            if command.is_empty() {
+                continue;
+            }
+            self.last_command = command.clone();
+
+            let words: Vec<&str> = command.split_whitespace().collect();
+            if matches!(words[0], "quit" | "q") {
+                return Ok(());
+            }
+            if self.execute_command(chip8, &words, output)? == Stop::Ended {
+                writeln!(output, "program ended")?;
+                return Ok(());
+            }
+        }
+    }
+
+    fn execute_command<W: Write>(&mut self, chip8: &mut Chip8, words: &[&str], output: &mut W) -> io::Result<Stop> {
+        match words {
+            ["step" | "s"] => return self.step(chip8, 1, output),
+            ["step" | "s", count] => match parse_number(count) {
+                Some(count) => return self.step(chip8, count, output),
+                None => writeln!(output, "not a number: {}", count)?,
+            },
+            ["continue" | "c"] => return self.continue_execution(chip8, output),
+            ["reverse-step" | "rs"] => self.reverse_step(chip8, 1, output)?,
+            ["reverse-step" | "rs", count] => match parse_number(count) {
+                Some(count) => self.reverse_step(chip8, count, output)?,
+                None => writeln!(output, "not a number: {}", count)?,
+            },
+            ["reverse-continue" | "rc"] => self.reverse_continue(chip8, output)?,
+            ["break" | "b", address] => match parse_hex(address) {
+                Some(address) => {
+                    self.breakpoints.insert(address);
+                    writeln!(output, "breakpoint at {:03X}", address)?;
+                },
+                None => writeln!(output, "not an address: {}", address)?,
+            },
+            ["break" | "b", "if", condition @ ..] => match Condition::parse(condition) {
+                Some(condition) => {
+                    writeln!(output, "condition #{}: {}", self.conditions.len(), condition.text)?;
+                    self.add_condition(chip8, condition);
+                },
+                None => writeln!(output, "usage: break if <V0 - VF | I | PC> <== | != | < | <= | > | >=> <value>")?,
+            },
+            ["break" | "b"] => {
+                for address in &self.breakpoints {
+                    writeln!(output, "breakpoint at {:03X}", address)?;
+                }
+                for (index, condition) in self.conditions.iter().enumerate() {
+                    writeln!(output, "condition #{}: {}", index, condition.text)?;
+                }
+            },
+            ["delete" | "d"] => {
+                self.breakpoints.clear();
+                self.watchpoints.clear();
+                self.conditions.clear();
+                writeln!(output, "deleted all breakpoints, watchpoints and conditions")?;
+            },
+            ["delete" | "d", number] if number.starts_with('#') => match parse_number(&number[1..]) {
+                Some(index) if index < self.conditions.len() => {
+                    let condition = self.conditions.remove(index);
+                    writeln!(output, "deleted condition {}", condition.text)?;
+                },
+                _ => writeln!(output, "no condition {}", number)?,
+            },
+            ["delete" | "d", address] => match parse_hex(address) {
+                Some(address) if self.breakpoints.remove(&address) => writeln!(output, "deleted breakpoint at {:03X}", address)?,
+                _ => writeln!(output, "no breakpoint at {}", address)?,
+            },
+            ["watch"] => {
+                for watchpoint in &self.watchpoints {
+                    writeln!(output, "{:?} watchpoint at {:03X} - {:03X}", watchpoint.kind, watchpoint.start,
+                             watchpoint.start + watchpoint.length - 1)?;
+                }
+            },
+            [command @ ("watch" | "rwatch" | "awatch"), address, length @ ..] if length.len() <= 1 => {
+                let kind = match *command {
+                    "watch" => WatchKind::Write,
+                    "rwatch" => WatchKind::Read,
+                    _ => WatchKind::Access,
+                };
+                let length = length.first().map_or(Some(1), |length| parse_number(length));
+                match (parse_hex(address), length) {
+                    (Some(start), Some(length)) if length > 0 && start < chip8.memory().len() => {
+                        match start.checked_add(length) {
+                            Some(end) => {
+                                // a range past the end of memory watches up to the last byte
+                                let length = end.min(chip8.memory().len()) - start;
+                                self.watchpoints.push(Watchpoint { start, length, kind });
+                                writeln!(output, "{:?} watchpoint at {:03X} - {:03X}", kind, start, start + length - 1)?;
+                            },
+                            None => writeln!(output, "length out of range: {}", length)?,
+                        }
+                    },
+                    _ => writeln!(output, "usage: {} <addr> [len]", command)?,
+                }
+            },
+            ["unwatch"] => {
+                self.watchpoints.clear();
+                writeln!(output, "deleted all watchpoints")?;
+            },
+            ["unwatch", address] => match parse_hex(address) {
+                Some(address) => {
+                    let before = self.watchpoints.len();
+                    self.watchpoints.retain(|watchpoint| !watchpoint.covers(address));
+                    writeln!(output, "deleted {} watchpoint(s)", before - self.watchpoints.len())?;
+                },
+                None => writeln!(output, "not an address: {}", address)?,
+            },
+            ["regs" | "r"] => self.show_registers(chip8, output)?,
+            ["stack"] => {
+                if chip8.stack().is_empty() {
+                    writeln!(output, "stack is empty")?;
+                }
+                for (depth, address) in chip8.stack().iter().enumerate().rev() {
+                    writeln!(output, "#{:<2} {:03X}", depth, address)?;
+                }
+            },
+            ["mem" | "m", address] => self.show_memory(chip8, address, None, output)?,
+            ["mem" | "m", address, length] => self.show_memory(chip8, address, Some(length), output)?,
+            ["disasm" | "x"] => self.show_disassembly(chip8, chip8.program_counter(), DEFAULT_DISASM_COUNT, output)?,
+            ["disasm" | "x", address] | ["disasm" | "x", address, _] => {
+                let count = words.get(2).map_or(Some(DEFAULT_DISASM_COUNT), |count| parse_number(count));
+                match (parse_hex(address), count) {
+                    (Some(address), Some(count)) => self.show_disassembly(chip8, address, count, output)?,
+                    _ => writeln!(output, "usage: disasm [addr] [count]")?,
+                }
+            },
+            ["set", register, value] => self.set(chip8, register, value, output)?,
+            [command @ ("press" | "release"), key] => match u8::from_str_radix(key, 16) {
+                Ok(key) if key < 16 => {
+                    if *command == "press" {
+                        chip8.keypad_mut().press_key(key.into());
+                    } else {
+                        chip8.keypad_mut().release_key(key.into());
+                    }
+                    writeln!(output, "key {:X} {}", key, if *command == "press" { "down" } else { "up" })?;
+                },
+                _ => writeln!(output, "usage: {} <0 - F>", command)?,
+            },
+            ["screen"] => chip8.screen().write_pixels(output)?,
+            ["screenshot", path, scale @ ..] if scale.len() <= 1 => {
+                match scale.first().map_or(Some(self.screenshot_scale), |scale| parse_number(scale)) {
+                    Some(scale) if (1..=MAX_SCALE).contains(&scale) => match screenshot::save(chip8.screen(), &self.palette, scale, path.as_ref()) {
+                        Ok(()) => writeln!(output, "saved screenshot to {}", path)?,
+                        Err(error) => writeln!(output, "{}", error)?,
+                    },
+                    _ => writeln!(output, "usage: screenshot <file> [scale from 1 to {}]", MAX_SCALE)?,
+                }
+            },
+            ["savestate", path] => match fs::write(path, chip8.save_state()) {
+                Ok(()) => writeln!(output, "saved state to {}", path)?,
+                Err(error) => writeln!(output, "{}", error)?,
+            },
+            ["loadstate", path] => match fs::read(path).map_err(Into::into).and_then(|state| chip8.load_state(&state)) {
+                Ok(()) => writeln!(output, "loaded state from {}, pc {:03X}", path, chip8.program_counter())?,
+                Err(error) => writeln!(output, "{}", error)?,
+            },
+            ["help" | "h"] => writeln!(output, "{}", HELP)?,
+            _ => writeln!(output, "unknown command, type 'help' for a list of commands")?,
+        }
+        Ok(Stop::Running)
+    }
+
+    fn execute_one<W: Write>(&mut self, chip8: &mut Chip8, output: &mut W) -> io::Result<Stop> {
+        let address = chip8.program_counter();
+        self.history.record(chip8);
+        match self.scheduler.step(chip8) {
+            Ok(true) => self.check_watches(chip8, address, output),
+            Ok(false) => Ok(Stop::Ended),
+            Err(error) => {
+                writeln!(output, "{}", error)?;
+                Ok(Stop::Error)
+            },
+        }
+    }
+
+    // looks at what the instruction at `address` just did
+    fn check_watches<W: Write>(&mut self, chip8: &Chip8, address: usize, output: &mut W) -> io::Result<Stop> {
+        let mut stop = Stop::Running;
+        for access in chip8.memory_accesses() {
+            if self.watchpoints.iter().any(|watchpoint| watchpoint.matches(*access)) {
+                let (verb, accessed) = match access {
+                    MemoryAccess::Read(accessed) => ("read", accessed),
+                    MemoryAccess::Write(accessed) => ("written", accessed),
+                };
+                writeln!(output, "watchpoint: {:03X} {} by the instruction at {:03X}", accessed, verb, address)?;
+                stop = Stop::Watchpoint;
+            }
+        }
+        for (index, condition) in self.conditions.iter_mut().enumerate() {
+            let is_true = condition.evaluate(chip8);
+            if is_true && !condition.was_true {
+                writeln!(output, "condition #{} ({}) became true after the instruction at {:03X}", index, condition.text, address)?;
+                stop = Stop::Watchpoint;
+            }
+            condition.was_true = is_true;
+        }
+        if stop != Stop::Running {
+            self.show_location(chip8, output)?;
+        }
+        Ok(stop)
+    }
+
+    fn step<W: Write>(&mut self, chip8: &mut Chip8, count: usize, output: &mut W) -> io::Result<Stop> {
+        for _ in 0..count {
+            let stop = self.execute_one(chip8, output)?;
+            if stop != Stop::Running {
+                // a watchpoint already showed where we are
+                return Ok(stop);
+            }
+        }
+        self.show_location(chip8, output)?;
+        Ok(Stop::Running)
+    }
+
+    fn continue_execution<W: Write>(&mut self, chip8: &mut Chip8, output: &mut W) -> io::Result<Stop> {
+        // a Ctrl-C at the prompt is not meant for this run
+        self.interrupted.store(false, Ordering::SeqCst);
+        loop {
+            let stop = self.execute_one(chip8, output)?;
+            if stop != Stop::Running {
+                return Ok(stop);
+            }
+            if self.interrupted.swap(false, Ordering::SeqCst) {
+                writeln!(output, "interrupted")?;
+                self.show_location(chip8, output)?;
+                return Ok(Stop::Breakpoint);
+            }
+            if self.breakpoints.contains(&chip8.program_counter()) {
+                writeln!(output, "breakpoint at {:03X}", chip8.program_counter())?;
+                self.show_location(chip8, output)?;
+                return Ok(Stop::Breakpoint);
+            }
+            // nothing changes the keypad while running here, so a waiting FX0A would never finish
+            match chip8.key_wait() {
+                Some(KeyWait::Press(_)) if chip8.keypad().pressed_key().is_none() => {
+                    writeln!(output, "waiting for a key, 'press' one to go on")?;
+                    self.show_location(chip8, output)?;
+                    return Ok(Stop::KeyWait);
+                },
+                Some(KeyWait::Release(_, key)) if chip8.keypad().is_pressed(key.into()) => {
+                    writeln!(output, "waiting for key {:X} to be released, 'release {:X}' to go on", key, key)?;
+                    self.show_location(chip8, output)?;
+                    return Ok(Stop::KeyWait);
+                },
+                _ => {},
+            }
+            if self.scheduler.is_frame_start() {
+                self.scheduler.wait_for_next_frame();
+            }
+        }
+    }
+
+    fn reverse_step<W: Write>(&mut self, chip8: &mut Chip8, count: usize, output: &mut W) -> io::Result<()> {
+        for _ in 0..count {
+            match self.history.rewind(chip8) {
+                Ok(true) => {},
+                Ok(false) => {
+                    writeln!(output, "no more history")?;
+                    break;
+                },
+                Err(error) => {
+                    writeln!(output, "{}", error)?;
+                    break;
+                },
+            }
+        }
+        self.reset_conditions(chip8);
+        self.show_location(chip8, output)
+    }
+
+    fn reverse_continue<W: Write>(&mut self, chip8: &mut Chip8, output: &mut W) -> io::Result<()> {
+        loop {
+            match self.history.rewind(chip8) {
+                Ok(true) => {},
+                Ok(false) => {
+                    writeln!(output, "no more history")?;
+                    break;
+                },
+                Err(error) => {
+                    writeln!(output, "{}", error)?;
+                    break;
+                },
+            }
+            if self.breakpoints.contains(&chip8.program_counter()) {
+                writeln!(output, "breakpoint at {:03X}", chip8.program_counter())?;
+                break;
+            }
+        }
+        self.reset_conditions(chip8);
+        self.show_location(chip8, output)
+    }
+
+    // so going back over a condition does not trigger it when running forward again
+    fn reset_conditions(&mut self, chip8: &Chip8) {
+        for condition in &mut self.conditions {
+            condition.was_true = condition.evaluate(chip8);
+        }
+    }
+
+    fn show_location<W: Write>(&self, chip8: &Chip8, output: &mut W) -> io::Result<()> {
+        self.show_disassembly(chip8, chip8.program_counter(), 1, output)
+    }
+
+    fn show_registers<W: Write>(&self, chip8: &Chip8, output: &mut W) -> io::Result<()> {
+        for (index, value) in chip8.registers().iter().enumerate() {
+            write!(output, "V{:X}={:02X}{}", index, value, if index % 8 == 7 { "\n" } else { " " })?;
+        }
+        writeln!(output, "I={:03X}  PC={:03X}  SP={}", chip8.register_i(), chip8.program_counter(), chip8.stack().len())
+    }
+
+    fn show_memory<W: Write>(&self, chip8: &Chip8, address: &str, length: Option<&&str>, output: &mut W) -> io::Result<()> {
+        let length = length.map_or(Some(DEFAULT_MEMORY_LENGTH), |length| parse_number(length));
+        let (start, length) = match (parse_hex(address), length) {
+            (Some(start), Some(length)) => (start, length),
+            _ => return writeln!(output, "usage: mem <addr> [len]"),
+        };
+        let memory = chip8.memory();
+        let end = start.saturating_add(length).min(memory.len());
+        if start >= end {
+            return writeln!(output, "address out of range");
+        }
+        for row_start in (start..end).step_by(16) {
+            let row = &memory[row_start..(row_start + 16).min(end)];
+            let bytes: Vec<String> = row.iter().map(|byte| format!("{:02X}", byte)).collect();
+            writeln!(output, "{:03X}: {}", row_start, bytes.join(" "))?;
+        }
+        Ok(())
+    }
+
+    fn show_disassembly<W: Write>(&self, chip8: &Chip8, start: usize, count: usize, output: &mut W) -> io::Result<()> {
+        let memory = chip8.memory();
+        let mut address = start;
+        for _ in 0..count {
+            let (mnemonic, length) = match decode_at(memory, address) {
+                Some(Ok(instruction)) => (instruction.to_string(), instruction.length()),
+                Some(Err(error)) => (format!("DW {:#06X}", error.opcode), 2),
+                None => break,
+            };
+            let opcode: String = memory[address..address + length].iter().map(|byte| format!("{:02X}", byte)).collect();
+            let marker = if address == chip8.program_counter() { '>' } else { ' ' };
+            let breakpoint = if self.breakpoints.contains(&address) { '*' } else { ' ' };
+            writeln!(output, "{}{} {:03X}  {}  {}", marker, breakpoint, address, opcode, mnemonic)?;
+            address += length;
+        }
+        Ok(())
+    }
+
+    fn set<W: Write>(&self, chip8: &mut Chip8, register: &str, value: &str, output: &mut W) -> io::Result<()> {
+        let value = match parse_hex(value) {
+            Some(value) => value,
+            None => return writeln!(output, "not a hex number: {}", value),
+        };
+        let register = register.to_ascii_uppercase();
+        match register.as_str() {
+            "I" if value <= 0xFFFF => chip8.set_register_i(value as u16),
+            "PC" if value < chip8.memory().len() => chip8.set_program_counter(value),
+            _ => match register.strip_prefix('V').and_then(|x| usize::from_str_radix(x, 16).ok()) {
+                Some(x) if x < 16 && value <= 0xFF => chip8.set_register(x, value as u8),
+                _ => return writeln!(output, "can't set {} to {:#X}", register, value),
+            },
+        }
+        self.show_registers(chip8, output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(debugger: &mut Debugger, chip8: &mut Chip8, command: &str) -> String {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let mut output = Vec::new();
+        debugger.execute_command(chip8, &words, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn keeps_watchpoints_inside_memory() {
+        let mut debugger = Debugger::new(10);
+        let mut chip8 = Chip8::new();
+        assert_eq!(run(&mut debugger, &mut chip8, "watch 200 0xFFFFFFFFFFFFFFFF"), "length out of range: 18446744073709551615\n");
+        assert_eq!(run(&mut debugger, &mut chip8, "watch 1000"), "usage: watch <addr> [len]\n");
+        assert!(debugger.watchpoints.is_empty());
+
+        assert_eq!(run(&mut debugger, &mut chip8, "awatch FF0 0x100"), "Access watchpoint at FF0 - FFF\n");
+        let watchpoint = debugger.watchpoints[0];
+        assert!(watchpoint.covers(0xFF0) && watchpoint.covers(0xFFF));
+        assert!(!watchpoint.covers(0xFEF) && !watchpoint.covers(0x1000) && !watchpoint.covers(usize::MAX));
+    }
+
+    #[test]
+    fn refuses_screenshot_scales_over_the_maximum() {
+        let mut debugger = Debugger::new(10);
+        let mut chip8 = Chip8::new();
+        let path = std::env::temp_dir().join("chip8-debugger-test.pbm");
+        let command = format!("screenshot {} {}", path.display(), MAX_SCALE + 1);
+        assert_eq!(run(&mut debugger, &mut chip8, &command), "usage: screenshot <file> [scale from 1 to 64]\n");
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn compares_with_every_operator() {
+        let mut chip8 = Chip8::new();
+        chip8.set_register(5, 0x3F);
+        let holds = |chip8: &Chip8, comparison: &str, value: &str| {
+            Condition::parse(&["V5", comparison, value]).unwrap().evaluate(chip8)
+        };
+        assert!(holds(&chip8, "==", "3F") && !holds(&chip8, "==", "40"));
+        assert!(holds(&chip8, "!=", "40") && !holds(&chip8, "!=", "3F"));
+        assert!(holds(&chip8, "<", "40") && !holds(&chip8, "<", "3F"));
+        assert!(holds(&chip8, "<=", "3F") && !holds(&chip8, "<=", "3E"));
+        assert!(holds(&chip8, ">", "3E") && !holds(&chip8, ">", "3F"));
+        assert!(holds(&chip8, ">=", "3F") && !holds(&chip8, ">=", "40"));
+        assert!(Condition::parse(&["V5", "=>", "3F"]).is_none());
+        assert!(Condition::parse(&["V5", "=", "3F"]).is_none());
+    }
+}
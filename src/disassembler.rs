@@ -0,0 +1,159 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::chip8::PROGRAM_START;
+use crate::instruction::{decode_at, length_at, Instruction};
+
+const BYTES_PER_DATA_LINE: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    // ordered by priority, when an address is referenced in more than one way
+    Data,
+    Jump,
+    Subroutine,
+}
+
+struct Analysis {
+    code: BTreeMap<usize, Instruction>,
+    labels: BTreeMap<usize, LabelKind>,
+}
+
+fn add_label(labels: &mut BTreeMap<usize, LabelKind>, address: u16, kind: LabelKind) {
+    let entry = labels.entry(address as usize).or_insert(kind);
+    *entry = (*entry).max(kind);
+}
+
+// follows the control flow from the entry point, everything that is never reached is treated as data
+fn analyze(rom: &[u8]) -> Analysis {
+    let fetch = |address: usize| match address.checked_sub(PROGRAM_START) {
+        Some(offset) => decode_at(rom, offset),
+        None => None,
+    };
+
+    let mut data_references: BTreeSet<usize> = BTreeSet::new();
+    loop {
+        let mut code = BTreeMap::new();
+        let mut labels = BTreeMap::new();
+        let mut branch_targets = BTreeSet::new();
+
+        let mut worklist = vec![PROGRAM_START];
+        while let Some(mut address) = worklist.pop() {
+            loop {
+                if code.contains_key(&address) {
+                    break;
+                }
+                // falling through into bytes that are loaded into I means we ran into data
+                if data_references.contains(&address) && !branch_targets.contains(&address) && address != PROGRAM_START {
+                    break;
+                }
+                let instruction = match fetch(address) {
+                    Some(Ok(instruction)) => instruction,
+                    _ => break,
+                };
+                code.insert(address, instruction);
+                let next = address + instruction.length();
+
+                match instruction {
+                    Instruction::Jp(nnn) => {
+                        add_label(&mut labels, nnn, LabelKind::Jump);
+                        branch_targets.insert(nnn as usize);
+                        worklist.push(nnn as usize);
+                        break;
+                    },
+                    Instruction::JpV0(nnn) => {
+                        // the offset in v0 is unknown, so only the base of the jump table is followed
+                        add_label(&mut labels, nnn, LabelKind::Jump);
+                        branch_targets.insert(nnn as usize);
+                        worklist.push(nnn as usize);
+                        break;
+                    },
+                    Instruction::Call(nnn) => {
+                        add_label(&mut labels, nnn, LabelKind::Subroutine);
+                        branch_targets.insert(nnn as usize);
+                        worklist.push(nnn as usize);
+                    },
+                    Instruction::Ret | Instruction::Exit => break,
+                    Instruction::LdI(nnn) | Instruction::LdILong(nnn) => {
+                        add_label(&mut labels, nnn, LabelKind::Data);
+                    },
+                    Instruction::SeByte(..) | Instruction::SneByte(..) |
+                    Instruction::SeReg(..) | Instruction::SneReg(..) |
+                    Instruction::Skp(_) | Instruction::Sknp(_) => {
+                        worklist.push(next + length_at(rom, next - PROGRAM_START));
+                    },
+                    _ => {},
+                }
+                address = next;
+            }
+        }
+
+        // a newly discovered data reference may cut a fall-through path short, so run again until nothing changes
+        let references: BTreeSet<usize> = labels.iter()
+                                                .filter(|(_, kind)| **kind == LabelKind::Data)
+                                                .map(|(address, _)| *address)
+                                                .collect();
+        if references.is_subset(&data_references) {
+            return Analysis { code, labels };
+        }
+        data_references.extend(references);
+    }
+}
+
+// produces an assembler-ready listing of a ROM loaded at 0x200, with the address and raw opcode of every line in a comment
+pub fn disassemble(rom: &[u8]) -> String {
+    let analysis = analyze(rom);
+    let end = PROGRAM_START + rom.len();
+
+    // lay out the lines first, so only labels that land on the start of a line are used
+    let mut lines: Vec<(usize, usize)> = Vec::new(); // (address, length)
+    let mut address = PROGRAM_START;
+    while address < end {
+        if let Some(instruction) = analysis.code.get(&address) {
+            lines.push((address, instruction.length()));
+            address += instruction.length();
+            continue;
+        }
+        let mut length = 1;
+        while length < BYTES_PER_DATA_LINE
+              && address + length < end
+              && !analysis.code.contains_key(&(address + length))
+              && !analysis.labels.contains_key(&(address + length)) {
+            length += 1;
+        }
+        lines.push((address, length));
+        address += length;
+    }
+
+    let labels: BTreeMap<usize, String> = analysis.labels.iter()
+        .filter(|(address, _)| lines.binary_search_by_key(*address, |line| line.0).is_ok())
+        .map(|(address, kind)| {
+            let prefix = match kind {
+                LabelKind::Subroutine => "sub",
+                LabelKind::Jump => "loc",
+                LabelKind::Data => "data",
+            };
+            (*address, format!("{}_{:03X}", prefix, address))
+        })
+        .collect();
+    let address_name = |nnn: u16| match labels.get(&(nnn as usize)) {
+        Some(label) => label.clone(),
+        None => format!("{:#05X}", nnn),
+    };
+
+    let mut listing = String::new();
+    for (address, length) in lines {
+        if let Some(label) = labels.get(&address) {
+            writeln!(listing, "{}:", label).unwrap();
+        }
+        let bytes = &rom[address - PROGRAM_START..address - PROGRAM_START + length];
+        if let Some(instruction) = analysis.code.get(&address) {
+            let opcode: String = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            writeln!(listing, "    {:<27} ; {:03X}  {}", instruction.mnemonic(address_name), address, opcode).unwrap();
+        } else {
+            let values: Vec<String> = bytes.iter().map(|byte| format!("{:#04X}", byte)).collect();
+            writeln!(listing, "    {:<27} ; {:03X}", format!("DB {}", values.join(", ")), address).unwrap();
+        }
+    }
+    listing
+}
@@ -0,0 +1,334 @@
+use std::fmt;
+
+// register indices are 0x0 - 0xF, addresses are 12 bits
+// the SUPER-CHIP and XO-CHIP instructions are decoded on every platform, Chip8 decides whether they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    ScrollDown(u8),         // 00CN
+    ScrollUp(u8),           // 00DN
+    Cls,                    // 00E0
+    Ret,                    // 00EE
+    ScrollRight,            // 00FB
+    ScrollLeft,             // 00FC
+    Exit,                   // 00FD
+    LowRes,                 // 00FE
+    HighRes,                // 00FF
+    Jp(u16),                // 1NNN
+    Call(u16),              // 2NNN
+    SeByte(usize, u8),      // 3XNN
+    SneByte(usize, u8),     // 4XNN
+    SeReg(usize, usize),    // 5XY0
+    StoreRange(usize, usize), // 5XY2
+    LoadRange(usize, usize),  // 5XY3
+    Ld(usize, u8),          // 6XNN
+    Add(usize, u8),         // 7XNN
+    LdReg(usize, usize),    // 8XY0
+    Or(usize, usize),       // 8XY1
+    And(usize, usize),      // 8XY2
+    Xor(usize, usize),      // 8XY3
+    AddReg(usize, usize),   // 8XY4
+    Sub(usize, usize),      // 8XY5
+    Shr(usize, usize),      // 8XY6
+    Subn(usize, usize),     // 8XY7
+    Shl(usize, usize),      // 8XYE
+    SneReg(usize, usize),   // 9XY0
+    LdI(u16),               // ANNN
+    JpV0(u16),              // BNNN
+    Rnd(usize, u8),         // CXNN
+    Drw(usize, usize, u8),  // DXYN
+    Skp(usize),             // EX9E
+    Sknp(usize),            // EXA1
+    LdILong(u16),           // F000 NNNN
+    Plane(u8),              // FN01
+    LdAudio,                // F002
+    LdDelay(usize),         // FX07
+    LdKey(usize),           // FX0A
+    SetDelay(usize),        // FX15
+    SetSound(usize),        // FX18
+    AddI(usize),            // FX1E
+    LdFont(usize),          // FX29
+    LdBigFont(usize),       // FX30
+    LdBcd(usize),           // FX33
+    LdPitch(usize),         // FX3A
+    StoreRegs(usize),       // FX55
+    LoadRegs(usize),        // FX65
+    StoreFlags(usize),      // FX75
+    LoadFlags(usize),       // FX85
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NON VALID INSTRUCTION {:04X}", self.opcode)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+// F000 NNNN is the only instruction made of two words, decode() can't see its operand
+pub const LONG_PREFIX: u16 = 0xF000;
+
+// decodes the instruction at `address`, None when it runs past the end of memory
+pub fn decode_at(memory: &[u8], address: usize) -> Option<Result<Instruction, DecodeError>> {
+    let opcode = word_at(memory, address)?;
+    if opcode == LONG_PREFIX {
+        return Some(Ok(Instruction::LdILong(word_at(memory, address + 2)?)));
+    }
+    Some(decode(opcode))
+}
+
+// how many bytes a skip jumps over when it skips the instruction at `address`
+pub fn length_at(memory: &[u8], address: usize) -> usize {
+    if word_at(memory, address) == Some(LONG_PREFIX) { 4 } else { 2 }
+}
+
+fn word_at(memory: &[u8], address: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*memory.get(address)?, *memory.get(address.checked_add(1)?)?]))
+}
+
+pub fn decode(opcode: u16) -> Result<Instruction, DecodeError> {
+    // Split the opcode into nibbles (4-bit values)
+    let nibbles = (
+        ((opcode & 0xF000) >> 12) as u8,
+        ((opcode & 0x0F00) >> 8) as usize,
+        ((opcode & 0x00F0) >> 4) as usize,
+        (opcode & 0x000F) as u8,
+    );
+    let nnn = opcode & 0x0FFF;
+    let nn = (opcode & 0x00FF) as u8;
+    let (x, y, n) = (nibbles.1, nibbles.2, nibbles.3);
+
+    let instruction = match nibbles.0 {
+        0x0 => match opcode {
+            0x00C0..=0x00CF => Instruction::ScrollDown(n),
+            0x00D0..=0x00DF => Instruction::ScrollUp(n),
+            0x00E0 => Instruction::Cls,
+            0x00EE => Instruction::Ret,
+            0x00FB => Instruction::ScrollRight,
+            0x00FC => Instruction::ScrollLeft,
+            0x00FD => Instruction::Exit,
+            0x00FE => Instruction::LowRes,
+            0x00FF => Instruction::HighRes,
+            _ => return Err(DecodeError { opcode }),
+        },
+        0x1 => Instruction::Jp(nnn),
+        0x2 => Instruction::Call(nnn),
+        0x3 => Instruction::SeByte(x, nn),
+        0x4 => Instruction::SneByte(x, nn),
+        0x5 => match n {
+            0x0 => Instruction::SeReg(x, y),
+            0x2 => Instruction::StoreRange(x, y),
+            0x3 => Instruction::LoadRange(x, y),
+            _ => return Err(DecodeError { opcode }),
+        },
+        0x6 => Instruction::Ld(x, nn),
+        0x7 => Instruction::Add(x, nn),
+        0x8 => match n {
+            0x0 => Instruction::LdReg(x, y),
+            0x1 => Instruction::Or(x, y),
+            0x2 => Instruction::And(x, y),
+            0x3 => Instruction::Xor(x, y),
+            0x4 => Instruction::AddReg(x, y),
+            0x5 => Instruction::Sub(x, y),
+            0x6 => Instruction::Shr(x, y),
+            0x7 => Instruction::Subn(x, y),
+            0xE => Instruction::Shl(x, y),
+            _ => return Err(DecodeError { opcode }),
+        },
+        0x9 if n == 0x0 => Instruction::SneReg(x, y),
+        0xA => Instruction::LdI(nnn),
+        0xB => Instruction::JpV0(nnn),
+        0xC => Instruction::Rnd(x, nn),
+        0xD => Instruction::Drw(x, y, n),
+        0xE => match nn {
+            0x9E => Instruction::Skp(x),
+            0xA1 => Instruction::Sknp(x),
+            _ => return Err(DecodeError { opcode }),
+        },
+        0xF => match nn {
+            0x01 => Instruction::Plane(x as u8),
+            0x02 if x == 0 => Instruction::LdAudio,
+            0x07 => Instruction::LdDelay(x),
+            0x0A => Instruction::LdKey(x),
+            0x15 => Instruction::SetDelay(x),
+            0x18 => Instruction::SetSound(x),
+            0x1E => Instruction::AddI(x),
+            0x29 => Instruction::LdFont(x),
+            0x30 => Instruction::LdBigFont(x),
+            0x33 => Instruction::LdBcd(x),
+            0x3A => Instruction::LdPitch(x),
+            0x55 => Instruction::StoreRegs(x),
+            0x65 => Instruction::LoadRegs(x),
+            0x75 => Instruction::StoreFlags(x),
+            0x85 => Instruction::LoadFlags(x),
+            _ => return Err(DecodeError { opcode }),
+        },
+        _ => return Err(DecodeError { opcode }),
+    };
+    Ok(instruction)
+}
+
+impl Instruction {
+    // formats the instruction in Cowgod/Chipper syntax, `address` names the NNN operand (e.g. a label)
+    pub fn mnemonic<F: Fn(u16) -> String>(&self, address: F) -> String {
+        match *self {
+            Instruction::ScrollDown(n) => format!("SCD {}", n),
+            Instruction::ScrollUp(n) => format!("SCU {}", n),
+            Instruction::Cls => "CLS".to_string(),
+            Instruction::Ret => "RET".to_string(),
+            Instruction::ScrollRight => "SCR".to_string(),
+            Instruction::ScrollLeft => "SCL".to_string(),
+            Instruction::Exit => "EXIT".to_string(),
+            Instruction::LowRes => "LOW".to_string(),
+            Instruction::HighRes => "HIGH".to_string(),
+            Instruction::Jp(nnn) => format!("JP {}", address(nnn)),
+            Instruction::Call(nnn) => format!("CALL {}", address(nnn)),
+            Instruction::SeByte(x, nn) => format!("SE V{:X}, {:#04X}", x, nn),
+            Instruction::SneByte(x, nn) => format!("SNE V{:X}, {:#04X}", x, nn),
+            Instruction::SeReg(x, y) => format!("SE V{:X}, V{:X}", x, y),
+            Instruction::StoreRange(x, y) => format!("SAVE V{:X}, V{:X}", x, y),
+            Instruction::LoadRange(x, y) => format!("LOAD V{:X}, V{:X}", x, y),
+            Instruction::Ld(x, nn) => format!("LD V{:X}, {:#04X}", x, nn),
+            Instruction::Add(x, nn) => format!("ADD V{:X}, {:#04X}", x, nn),
+            Instruction::LdReg(x, y) => format!("LD V{:X}, V{:X}", x, y),
+            Instruction::Or(x, y) => format!("OR V{:X}, V{:X}", x, y),
+            Instruction::And(x, y) => format!("AND V{:X}, V{:X}", x, y),
+            Instruction::Xor(x, y) => format!("XOR V{:X}, V{:X}", x, y),
+            Instruction::AddReg(x, y) => format!("ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub(x, y) => format!("SUB V{:X}, V{:X}", x, y),
+            Instruction::Shr(x, y) => format!("SHR V{:X}, V{:X}", x, y),
+            Instruction::Subn(x, y) => format!("SUBN V{:X}, V{:X}", x, y),
+            Instruction::Shl(x, y) => format!("SHL V{:X}, V{:X}", x, y),
+            Instruction::SneReg(x, y) => format!("SNE V{:X}, V{:X}", x, y),
+            Instruction::LdI(nnn) => format!("LD I, {}", address(nnn)),
+            Instruction::JpV0(nnn) => format!("JP V0, {}", address(nnn)),
+            Instruction::Rnd(x, nn) => format!("RND V{:X}, {:#04X}", x, nn),
+            Instruction::Drw(x, y, n) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::Skp(x) => format!("SKP V{:X}", x),
+            Instruction::Sknp(x) => format!("SKNP V{:X}", x),
+            Instruction::LdILong(nnnn) => format!("LD I, LONG {}", address(nnnn)),
+            Instruction::Plane(n) => format!("PLANE {}", n),
+            Instruction::LdAudio => "LD AUDIO, [I]".to_string(),
+            Instruction::LdDelay(x) => format!("LD V{:X}, DT", x),
+            Instruction::LdKey(x) => format!("LD V{:X}, K", x),
+            Instruction::SetDelay(x) => format!("LD DT, V{:X}", x),
+            Instruction::SetSound(x) => format!("LD ST, V{:X}", x),
+            Instruction::AddI(x) => format!("ADD I, V{:X}", x),
+            Instruction::LdFont(x) => format!("LD F, V{:X}", x),
+            Instruction::LdBigFont(x) => format!("LD HF, V{:X}", x),
+            Instruction::LdBcd(x) => format!("LD B, V{:X}", x),
+            Instruction::LdPitch(x) => format!("LD PITCH, V{:X}", x),
+            Instruction::StoreRegs(x) => format!("LD [I], V{:X}", x),
+            Instruction::LoadRegs(x) => format!("LD V{:X}, [I]", x),
+            Instruction::StoreFlags(x) => format!("LD R, V{:X}", x),
+            Instruction::LoadFlags(x) => format!("LD V{:X}, R", x),
+        }
+    }
+
+    // 2, or 4 for F000 NNNN
+    pub fn length(&self) -> usize {
+        match self {
+            Instruction::LdILong(_) => 4,
+            _ => 2,
+        }
+    }
+
+    // the bytes of the instruction as they are stored in memory
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match *self {
+            Instruction::LdILong(nnnn) => [LONG_PREFIX.to_be_bytes(), nnnn.to_be_bytes()].concat(),
+            _ => self.encode().to_be_bytes().to_vec(),
+        }
+    }
+
+    // the first word of the instruction, to_bytes() also has the operand of F000 NNNN
+    pub fn encode(&self) -> u16 {
+        let xy = |opcode: u16, x: usize, y: usize, n: u16| opcode | ((x as u16) << 8) | ((y as u16) << 4) | n;
+        let xnn = |opcode: u16, x: usize, nn: u8| opcode | ((x as u16) << 8) | u16::from(nn);
+        match *self {
+            Instruction::ScrollDown(n) => 0x00C0 | u16::from(n & 0xF),
+            Instruction::ScrollUp(n) => 0x00D0 | u16::from(n & 0xF),
+            Instruction::Cls => 0x00E0,
+            Instruction::Ret => 0x00EE,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowRes => 0x00FE,
+            Instruction::HighRes => 0x00FF,
+            Instruction::Jp(nnn) => 0x1000 | (nnn & 0x0FFF),
+            Instruction::Call(nnn) => 0x2000 | (nnn & 0x0FFF),
+            Instruction::SeByte(x, nn) => xnn(0x3000, x, nn),
+            Instruction::SneByte(x, nn) => xnn(0x4000, x, nn),
+            Instruction::SeReg(x, y) => xy(0x5000, x, y, 0x0),
+            Instruction::StoreRange(x, y) => xy(0x5000, x, y, 0x2),
+            Instruction::LoadRange(x, y) => xy(0x5000, x, y, 0x3),
+            Instruction::Ld(x, nn) => xnn(0x6000, x, nn),
+            Instruction::Add(x, nn) => xnn(0x7000, x, nn),
+            Instruction::LdReg(x, y) => xy(0x8000, x, y, 0x0),
+            Instruction::Or(x, y) => xy(0x8000, x, y, 0x1),
+            Instruction::And(x, y) => xy(0x8000, x, y, 0x2),
+            Instruction::Xor(x, y) => xy(0x8000, x, y, 0x3),
+            Instruction::AddReg(x, y) => xy(0x8000, x, y, 0x4),
+            Instruction::Sub(x, y) => xy(0x8000, x, y, 0x5),
+            Instruction::Shr(x, y) => xy(0x8000, x, y, 0x6),
+            Instruction::Subn(x, y) => xy(0x8000, x, y, 0x7),
+            Instruction::Shl(x, y) => xy(0x8000, x, y, 0xE),
+            Instruction::SneReg(x, y) => xy(0x9000, x, y, 0x0),
+            Instruction::LdI(nnn) => 0xA000 | (nnn & 0x0FFF),
+            Instruction::JpV0(nnn) => 0xB000 | (nnn & 0x0FFF),
+            Instruction::Rnd(x, nn) => xnn(0xC000, x, nn),
+            Instruction::Drw(x, y, n) => xy(0xD000, x, y, u16::from(n & 0xF)),
+            Instruction::Skp(x) => xnn(0xE000, x, 0x9E),
+            Instruction::Sknp(x) => xnn(0xE000, x, 0xA1),
+            Instruction::LdILong(_) => LONG_PREFIX,
+            Instruction::Plane(n) => xnn(0xF000, usize::from(n & 0xF), 0x01),
+            Instruction::LdAudio => 0xF002,
+            Instruction::LdDelay(x) => xnn(0xF000, x, 0x07),
+            Instruction::LdKey(x) => xnn(0xF000, x, 0x0A),
+            Instruction::SetDelay(x) => xnn(0xF000, x, 0x15),
+            Instruction::SetSound(x) => xnn(0xF000, x, 0x18),
+            Instruction::AddI(x) => xnn(0xF000, x, 0x1E),
+            Instruction::LdFont(x) => xnn(0xF000, x, 0x29),
+            Instruction::LdBigFont(x) => xnn(0xF000, x, 0x30),
+            Instruction::LdBcd(x) => xnn(0xF000, x, 0x33),
+            Instruction::LdPitch(x) => xnn(0xF000, x, 0x3A),
+            Instruction::StoreRegs(x) => xnn(0xF000, x, 0x55),
+            Instruction::LoadRegs(x) => xnn(0xF000, x, 0x65),
+            Instruction::StoreFlags(x) => xnn(0xF000, x, 0x75),
+            Instruction::LoadFlags(x) => xnn(0xF000, x, 0x85),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.mnemonic(|nnn| format!("{:#05X}", nnn)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_is_the_inverse_of_decode() {
+        for opcode in 0..=u16::MAX {
+            if let Ok(instruction) = decode(opcode) {
+                assert_eq!(instruction.encode(), opcode, "{:?}", instruction);
+                assert_eq!(decode(instruction.encode()), Ok(instruction));
+            }
+        }
+    }
+
+    #[test]
+    fn long_load_decodes_from_its_bytes() {
+        let instruction = Instruction::LdILong(0xBEEF);
+        assert_eq!(instruction.to_bytes(), [0xF0, 0x00, 0xBE, 0xEF]);
+        assert_eq!(decode_at(&instruction.to_bytes(), 0), Some(Ok(instruction)));
+        assert_eq!(length_at(&instruction.to_bytes(), 0), instruction.length());
+    }
+}
@@ -0,0 +1,176 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use toml::{Table, Value};
+
+// the hex keys in the order they sit on the COSMAC VIP keypad
+const KEYPAD_LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+pub const LAYOUTS: [&str; 3] = ["qwerty", "azerty", "dvorak"];
+
+#[derive(Debug)]
+pub enum KeyMapError {
+    Io(PathBuf, io::Error),
+    Toml(PathBuf, toml::de::Error),
+    Invalid(PathBuf, String),
+}
+
+impl fmt::Display for KeyMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyMapError::Io(path, error) => write!(f, "{}: FAILED TO READ THE KEY MAP: {}", path.display(), error),
+            KeyMapError::Toml(path, error) => write!(f, "{}: KEY MAP IS NOT VALID TOML: {}", path.display(), error),
+            KeyMapError::Invalid(path, message) => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for KeyMapError {}
+
+// which keyboard key stands for each of the 16 hex keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyMap {
+    keys: [char; 16],
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self::qwerty()
+    }
+}
+
+impl KeyMap {
+    // the keypad sits on the four left-most columns of the keyboard, whatever letters they carry
+    fn from_rows(rows: [&str; 4]) -> KeyMap {
+        let mut keys = [' '; 16];
+        for (hex_row, row) in KEYPAD_LAYOUT.iter().zip(rows) {
+            for (key, character) in hex_row.iter().zip(row.chars()) {
+                keys[usize::from(*key)] = character;
+            }
+        }
+        KeyMap { keys }
+    }
+
+    pub fn qwerty() -> KeyMap {
+        KeyMap::from_rows(["1234", "qwer", "asdf", "zxcv"])
+    }
+
+    pub fn azerty() -> KeyMap {
+        KeyMap::from_rows(["1234", "azer", "qsdf", "wxcv"])
+    }
+
+    pub fn dvorak() -> KeyMap {
+        KeyMap::from_rows(["1234", "',.p", "aoeu", ";qjk"])
+    }
+
+    pub fn layout(name: &str) -> Option<KeyMap> {
+        match name {
+            "qwerty" => Some(KeyMap::qwerty()),
+            "azerty" => Some(KeyMap::azerty()),
+            "dvorak" => Some(KeyMap::dvorak()),
+            _ => None,
+        }
+    }
+
+    // the hex key a typed character stands for, letters match in either case
+    pub fn key_for_char(&self, character: char) -> Option<u8> {
+        let character = character.to_ascii_lowercase();
+        self.keys.iter().position(|key| *key == character).map(|key| key as u8)
+    }
+
+    pub fn char_for_key(&self, key: u8) -> char {
+        self.keys[usize::from(key & 0xF)]
+    }
+
+    pub fn set(&mut self, key: u8, character: char) {
+        self.keys[usize::from(key & 0xF)] = character.to_ascii_lowercase();
+    }
+
+    // where the key map shared by every ROM lives, $XDG_CONFIG_HOME/chip8/keymap.toml or ~/.config/chip8/keymap.toml
+    pub fn global_path() -> Option<PathBuf> {
+        let config = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config.join("chip8").join("keymap.toml"))
+    }
+
+    // the overrides for one ROM sit next to it, `game.keymap.toml` for `game.ch8`
+    pub fn rom_path(rom: &Path) -> PathBuf {
+        rom.with_extension("keymap.toml")
+    }
+
+    // the global key map with the ROM's overrides on top, files that don't exist are skipped
+    pub fn load(rom: Option<&Path>) -> Result<KeyMap, KeyMapError> {
+        let mut keymap = KeyMap::default();
+        let paths = KeyMap::global_path().into_iter().chain(rom.map(KeyMap::rom_path));
+        for path in paths {
+            match fs::read_to_string(&path) {
+                Ok(text) => keymap.apply(&path, &text)?,
+                Err(error) if error.kind() == ErrorKind::NotFound => {},
+                Err(error) => return Err(KeyMapError::Io(path, error)),
+            }
+        }
+        Ok(keymap)
+    }
+
+    // a file may pick a base layout and then rebind single keys:
+    //   layout = "azerty"
+    //   [keys]
+    //   A = "y"
+    pub fn apply(&mut self, path: &Path, text: &str) -> Result<(), KeyMapError> {
+        let invalid = |message: String| KeyMapError::Invalid(path.to_path_buf(), message);
+        let table: Table = text.parse().map_err(|error| KeyMapError::Toml(path.to_path_buf(), error))?;
+        let mut keymap = *self;
+        for (name, value) in &table {
+            match (name.as_str(), value) {
+                ("layout", Value::String(layout)) => match KeyMap::layout(layout) {
+                    Some(layout) => keymap = layout,
+                    None => return Err(invalid(format!("UNKNOWN LAYOUT {}, EXPECTED ONE OF {}", layout, LAYOUTS.join(", ")))),
+                },
+                ("keys", Value::Table(_)) => {},
+                _ => return Err(invalid(format!("UNKNOWN SETTING {}", name))),
+            }
+        }
+        if let Some(Value::Table(keys)) = table.get("keys") {
+            for (hex, value) in keys {
+                let key = match u8::from_str_radix(hex, 16) {
+                    Ok(key) if hex.len() == 1 => key,
+                    _ => return Err(invalid(format!("{} IS NOT A HEX KEY, EXPECTED 0 - F", hex))),
+                };
+                let mut characters = value.as_str().unwrap_or_default().chars();
+                match (characters.next(), characters.next()) {
+                    (Some(character), None) if !character.is_whitespace() => keymap.set(key, character),
+                    _ => return Err(invalid(format!("KEY {} NEEDS A SINGLE CHARACTER", hex))),
+                }
+            }
+        }
+        for key in 0..16u8 {
+            let character = keymap.char_for_key(key);
+            if keymap.key_for_char(character) != Some(key) {
+                return Err(invalid(format!("'{}' IS BOUND TO MORE THAN ONE HEX KEY", character)));
+            }
+        }
+        *self = keymap;
+        Ok(())
+    }
+}
+
+// the keypad next to the keyboard keys standing in for it
+impl fmt::Display for KeyMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, row) in KEYPAD_LAYOUT.iter().enumerate() {
+            let hex: Vec<String> = row.iter().map(|key| format!("{:X}", key)).collect();
+            let keyboard: Vec<String> = row.iter().map(|key| self.char_for_key(*key).to_uppercase().to_string()).collect();
+            let arrow = if index == 1 { "->" } else { "  " };
+            writeln!(f, "{}  {}  {}", hex.join(" "), arrow, keyboard.join(" "))?;
+        }
+        Ok(())
+    }
+}
@@ -3,6 +3,12 @@ pub struct Keypad {
     keys: [bool; 16],
 }
 
+impl Default for Keypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Keypad {
     pub fn new() -> Keypad {
         Keypad { keys: [false; 16] }
@@ -20,6 +26,11 @@ impl Keypad {
         }
     }
 
+    // the lowest key that is down, if any
+    pub fn pressed_key(&self) -> Option<u8> {
+        self.keys.iter().position(|pressed| *pressed).map(|key| key as u8)
+    }
+
     pub fn is_pressed(&self, key: usize) -> bool {
         if key < 16 {
             self.keys[key]
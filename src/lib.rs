@@ -0,0 +1,25 @@
+pub mod assembler;
+pub mod audio;
+pub mod chip8;
+pub mod debugger;
+pub mod disassembler;
+pub mod instruction;
+pub mod keymap;
+pub mod keypad;
+pub mod octo;
+pub mod palette;
+pub mod opcodes;
+pub mod quirks;
+pub mod renderer;
+pub mod rewind;
+pub mod savestate;
+pub mod scheduler;
+pub mod screen;
+pub mod screenshot;
+#[cfg(feature = "sound")]
+pub mod sound;
+pub mod timers;
+
+pub use crate::chip8::Chip8;
+pub use crate::instruction::{decode, DecodeError, Instruction};
+pub use crate::opcodes::{Chip8EmulatorError, Chip8ErrorKind};
@@ -1,376 +1,186 @@
-use core::panic;
-use std::thread;
-use std::sync::{Arc, Mutex};
-use std::fs::File;
-use std::io::{Read, Result, ErrorKind, self};
-use std::time::Duration;
-use crossterm::{
-    execute,
-    terminal::{Clear, ClearType},
-};
-use timers::Timer;
-use num::cast::FromPrimitive;
-use std::path::PathBuf;
-
-use crate::opcodes::{
-                    add_instruction, sub_instruction,
-                    shl_instruction, shr_instruction,
-                    jump_instruction, call_instruction,
-                    jump_v0_instruction, rand_instruction,
-                    add_no_overflow_instruction,
-                    };
-use crate::keypad::Keypad;
-use crate::screen::{Screen, PixelState};
-use crate::timers::{DelayTimer, SoundTimer, decrement_timer};
-
-mod keypad;
-mod opcodes;
-mod screen;
-mod timers;
- 
-type Stack<T> = Vec<T>;
-
-fn execute_instruction(registers: &mut [u8; 16], register_i: &mut u16,
-                       screen: &mut Screen,
-                       keypad: &mut Keypad, memory: &mut [u8; 4096], stack: &mut Stack<u16>,
-                       instruction: u16, program_counter: &mut usize,
-                       delay_timer_arc: &Arc<Mutex<Box<dyn Timer + Send>>>,
-                       sound_timer_arc: &Arc<Mutex<Box<dyn Timer + Send>>>)
-{
-    // Split the opcode into nibbles (4-bit values)
-    let nibbles = (
-        ((instruction & 0xF000) >> 12) as u8,
-        ((instruction & 0x0F00) >> 8) as u8,
-        ((instruction & 0x00F0) >> 4) as u8,
-        (instruction & 0x000F) as u8,
-    );
-
-    match nibbles.0 {
-        0x0 => match nibbles.3 {
-            0x0 => {
-                screen.clear_screen();
+// raw mode and the alternate screen belong to this frontend, not to the library
+mod terminal;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::path::{Path, PathBuf};
+
+use signal_hook::consts::SIGINT;
+use signal_hook::flag;
+use crossterm::execute;
+use crossterm::cursor::MoveTo;
+use crossterm::style::Print;
+use crossterm::terminal::{Clear, ClearType};
+
+use chip8::Chip8;
+use chip8::chip8::{load_file_to_memory, PROGRAM_START, XO_MEMORY_SIZE};
+use chip8::assembler::{assemble, AssembleError};
+use chip8::audio::{AudioRenderer, Beeper, WavWriter, DEFAULT_SAMPLE_RATE};
+use chip8::debugger::Debugger;
+use chip8::disassembler::disassemble;
+use chip8::keymap::KeyMap;
+use chip8::octo;
+use chip8::palette::{ColorDepth, Palette, Rgb, THEMES};
+use chip8::quirks::{Platform, Quirks, PLATFORMS, PRESETS};
+use chip8::renderer::{self, Renderer, RENDERERS};
+use chip8::rewind::RewindBuffer;
+#[cfg(feature = "sound")]
+use chip8::sound::AudioDevice;
+use chip8::scheduler::{Pacing, Scheduler};
+use chip8::screenshot::{self, ImageFormat, DEFAULT_SCALE, MAX_SCALE};
+use chip8::timers::FRAME_DURATION;
+
+use terminal::{Hotkey, Terminal};
+
+const USAGE: &str = "usage: chip8 [--debug] [--load-state FILE] [--platform PLATFORM] [--quirks PRESET] [--quirk NAME=on|off]
+                   [--beep-frequency HZ] [--volume 0-100] [--audio-out FILE]
+                   [--ipf N] [--fast-forward FACTOR] [--uncapped] [--frames N] [--renderer RENDERER]
+                   [--theme THEME] [--fg COLOR] [--bg COLOR] [--color 0-3=COLOR]
+                   [--screenshot-scale N] [--screenshot-after-frames N FILE] [ROM]
+       chip8 --print-keymap [ROM]
+       chip8 disasm ROM
+       chip8 asm SOURCE OUTPUT
+       chip8 octo SOURCE OUTPUT";
+
+// draws the screen to stdout with the renderer picked by --renderer
+struct Display {
+    renderer: Box<dyn Renderer>,
+}
+
+impl Display {
+    fn new(renderer: Box<dyn Renderer>) -> Display {
+        Display { renderer }
+    }
+
+    fn render(&mut self, chip8: &Chip8) {
+        if let Err(error) = self.renderer.render(chip8.screen(), &mut io::stdout()) {
+            exit_with_error(format!("FAILED TO DRAW THE SCREEN: {}", error));
+        }
+    }
+
+    // a line of text under the display
+    fn show_status(&self, chip8: &Chip8, message: &str) {
+        let row = self.renderer.rows(chip8.screen()) as u16 + 1;
+        let _ = execute!(io::stdout(), MoveTo(0, row), Clear(ClearType::CurrentLine), Print(message));
+    }
+}
+
+#[derive(Default)]
+struct Options {
+    debug: bool,
+    load_state: Option<PathBuf>,
+    platform: Platform,
+    quirks: Quirks,
+    beeper: Beeper,
+    audio_out: Option<PathBuf>,
+    instructions_per_frame: Option<u32>, // the platform's when not given
+    pacing: Pacing,
+    frames: Option<u64>,
+    print_keymap: bool,
+    renderer: Option<String>, // halfblock when not given
+    palette: Palette,
+    screenshot_scale: Option<usize>, // DEFAULT_SCALE when not given
+    screenshot_after: Option<(u64, PathBuf)>, // runs without a terminal and exits after saving it
+}
+
+// "2=#FF8800", a pixel value from 0 to 3 and its colour
+fn parse_pixel_color(text: &str) -> Option<(usize, Rgb)> {
+    let (index, color) = text.split_once('=')?;
+    let index = index.parse::<usize>().ok().filter(|index| *index < 4)?;
+    Some((index, Rgb::parse(color)?))
+}
+
+// splits the command line into options and positional arguments
+fn parse_arguments(args: &[String]) -> Result<(Options, Vec<String>), String> {
+    let mut options = Options::default();
+    let mut positional = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--debug" => options.debug = true,
+            "--load-state" => match args.next() {
+                Some(path) => options.load_state = Some(PathBuf::from(path)),
+                None => return Err(format!("--load-state needs a file\n{}", USAGE)),
             },
-            0xE => {
-                let return_address = match stack.pop() {
-                    Some(address) => address,
-                    None => panic!("NO RETURN ADDRESS FOUND"),
-                };
-                *program_counter = return_address as usize;
+            // a platform brings its own quirks, later options can still change them
+            "--platform" => match args.next().and_then(|platform| Platform::from_name(platform)) {
+                Some(platform) => {
+                    options.platform = platform;
+                    options.quirks = platform.quirks();
+                },
+                None => return Err(format!("--platform needs one of {}", PLATFORMS.join(", "))),
             },
-            _ => {
-                println!("{:?}, {:?}", nibbles, instruction);
-                panic!("NON VALID INSTRUCTION");
+            "--quirks" => match args.next().and_then(|preset| Quirks::preset(preset)) {
+                Some(quirks) => options.quirks = quirks,
+                None => return Err(format!("--quirks needs one of {}", PRESETS.join(", "))),
             },
-        },
-        0x1 => {
-            let n1 = char::from_digit(nibbles.1 as u32, 16).unwrap().to_string();
-            let n2 = char::from_digit(nibbles.2 as u32, 16).unwrap().to_string();
-            let n3 = char::from_digit(nibbles.3 as u32, 16).unwrap().to_string();
-            let nnn = u16::from_str_radix(&(n1 + &n2 + &n3), 16).unwrap();
-            jump_instruction(program_counter, nnn);
-        },
-        0x2 => {
-            let n1 = char::from_digit(nibbles.1 as u32, 16).unwrap().to_string();
-            let n2 = char::from_digit(nibbles.2 as u32, 16).unwrap().to_string();
-            let n3 = char::from_digit(nibbles.3 as u32, 16).unwrap().to_string();
-            let nnn = u16::from_str_radix(&(n1 + &n2 + &n3), 16).unwrap();
-            match call_instruction(program_counter, nnn, stack){
-                Err(_) => panic!("ERROR: instruction failed at call instruction"),
-                _ => {},
-            };
-        },
-        0x3 => {
-            let n1 = char::from_digit(nibbles.2 as u32, 16).unwrap().to_string();
-            let n2 = char::from_digit(nibbles.3 as u32, 16).unwrap().to_string();
-            let nn = u8::from_str_radix(&(n1 + &n2), 16).unwrap();
-            let equals: bool = (*registers.get::<usize>(nibbles.1.into()).expect("NON VALID REGISTER")) == nn;
-            if equals {
-                *program_counter += 2;
-            }
-        },
-        0x4 => {
-            let n1 = char::from_digit(nibbles.2 as u32, 16).unwrap().to_string();
-            let n2 = char::from_digit(nibbles.3 as u32, 16).unwrap().to_string();
-            let nn = u8::from_str_radix(&(n1 + &n2), 16).unwrap();
-            let equals: bool = *registers.get::<usize>(nibbles.1.into()).expect("NON VALID REGISTER") == nn;
-            if !equals {
-                *program_counter += 2;
-            }
-        },
-        0x5 => {
-            let vx_value: u8 = *registers.get::<usize>(nibbles.1.into()).expect("NON VALID REGISTER");
-            let equals: bool = *registers.get::<usize>(nibbles.1.into()).expect("NON VALID REGISTER") == vx_value;
-            if equals {
-                *program_counter += 2;
-            }
-        },
-        0x6 => {
-            let n1 = char::from_digit(nibbles.2 as u32, 16).unwrap().to_string();
-            let n2 = char::from_digit(nibbles.3 as u32, 16).unwrap().to_string();
-            let nn = u8::from_str_radix(&(n1 + &n2), 16).unwrap();
-            registers[nibbles.1 as usize] = nn;
-        },
-        0x7 => {
-            let n1 = char::from_digit(nibbles.2 as u32, 16).unwrap().to_string();
-            let n2 = char::from_digit(nibbles.3 as u32, 16).unwrap().to_string();
-            let nn = u8::from_str_radix(&(n1 + &n2), 16).unwrap();
-            add_no_overflow_instruction(registers, nibbles.1.into(), nn);
-        },
-        0x8 => match nibbles.3 {
-            0x0 => {
-                let vy_value: u8 = *registers.get::<usize>(nibbles.1.into()).expect("NON VALID REGISTER");
-                registers[nibbles.1 as usize] = vy_value;
+            "--quirk" => match args.next().and_then(|quirk| quirk.split_once('=')) {
+                Some((name, "on")) => options.quirks.set(name, true)?,
+                Some((name, "off")) => options.quirks.set(name, false)?,
+                _ => return Err(format!("--quirk needs NAME=on or NAME=off\n{}", USAGE)),
             },
-            0x1 => {
-                let vy_value: u8 = *registers.get::<usize>(nibbles.1.into()).expect("NON VALID REGISTER");
-                registers[nibbles.1 as usize] = registers[nibbles.1 as usize] | vy_value;
+            "--beep-frequency" => match args.next().and_then(|frequency| frequency.parse::<f64>().ok()) {
+                Some(frequency) if frequency > 0.0 => options.beeper.frequency = frequency,
+                _ => return Err(format!("--beep-frequency needs a frequency in Hz\n{}", USAGE)),
             },
-            0x2 => {
-                let vy_value: u8 = *registers.get::<usize>(nibbles.1.into()).expect("NON VALID REGISTER");
-                registers[nibbles.1 as usize] = registers[nibbles.1 as usize] & vy_value;
+            "--volume" => match args.next().and_then(|volume| volume.parse::<u8>().ok()) {
+                Some(volume) if volume <= 100 => options.beeper.volume = volume,
+                _ => return Err(format!("--volume needs a percentage from 0 to 100\n{}", USAGE)),
             },
-            0x3 => {
-                let vy_value: u8 = *registers.get::<usize>(nibbles.1.into()).expect("NON VALID REGISTER");
-                registers[nibbles.1 as usize] = registers[nibbles.1 as usize] ^ vy_value;
+            "--audio-out" => match args.next() {
+                Some(path) => options.audio_out = Some(PathBuf::from(path)),
+                None => return Err(format!("--audio-out needs a file\n{}", USAGE)),
             },
-            0x4 => {
-                let vy_value: u8 = *registers.get::<usize>(nibbles.1.into()).expect("NON VALID REGISTER");
-                add_instruction(registers, nibbles.1.into(), vy_value);
+            "--ipf" => match args.next().and_then(|ipf| ipf.parse::<u32>().ok()) {
+                Some(ipf) if ipf > 0 => options.instructions_per_frame = Some(ipf),
+                _ => return Err(format!("--ipf needs a number of instructions per frame\n{}", USAGE)),
             },
-            0x5 => {
-                let vy_value: u8 = *registers.get::<usize>(nibbles.1.into()).expect("NON VALID REGISTER");
-                sub_instruction(registers, nibbles.1.into(), vy_value);
+            "--fast-forward" => match args.next().and_then(|factor| factor.parse::<u32>().ok()) {
+                Some(factor) if factor > 0 => options.pacing = Pacing::FastForward(factor),
+                _ => return Err(format!("--fast-forward needs a speed-up factor\n{}", USAGE)),
             },
-            0x6 => {
-                shr_instruction(registers, nibbles.1.into());
+            "--uncapped" => options.pacing = Pacing::Uncapped,
+            "--print-keymap" => options.print_keymap = true,
+            "--renderer" => match args.next().filter(|renderer| RENDERERS.contains(&renderer.as_str())) {
+                Some(renderer) => options.renderer = Some(renderer.clone()),
+                None => return Err(format!("--renderer needs one of {}", RENDERERS.join(", "))),
             },
-            0x7 => {
-                let vy_value: u8 = *registers.get::<usize>(nibbles.1.into()).expect("NON VALID REGISTER");
-                let vx_value: u8 = *registers.get::<usize>(nibbles.1.into()).expect("NON VALID REGISTER");
-                let (result, is_borrow) = vy_value.overflowing_sub(vx_value);
-                registers[0xF as usize] = (!is_borrow).into();
-                registers[nibbles.1 as usize] = result;
+            "--frames" => match args.next().and_then(|frames| frames.parse::<u64>().ok()) {
+                Some(frames) => options.frames = Some(frames),
+                None => return Err(format!("--frames needs a number of frames\n{}", USAGE)),
             },
-            0xE => {
-                shl_instruction(registers, nibbles.1.into());
+            // a theme replaces every colour, later options can still change single ones
+            "--theme" => match args.next().and_then(|theme| Palette::theme(theme)) {
+                Some(palette) => options.palette = palette,
+                None => return Err(format!("--theme needs one of {}", THEMES.join(", "))),
             },
-            _ => {
-                println!("{:?}, {:?}", nibbles, instruction);
-                panic!("NON VALID INSTRUCTION");
+            "--fg" | "--bg" => match args.next().and_then(|color| Rgb::parse(color)) {
+                Some(rgb) => options.palette.colors[usize::from(arg == "--fg")] = rgb,
+                None => return Err(format!("{} needs a colour like #33FF33\n{}", arg, USAGE)),
             },
-        },
-        0x9 => {
-            let vx_value: u8 = *registers.get::<usize>(nibbles.1.into()).expect("NON VALID REGISTER");
-            let equals = (*registers.get::<usize>(nibbles.2.into()).expect("NON VALID REGISTER")) == vx_value;
-            if !equals {
-                *program_counter += 2;
-            }
-        },
-        0xA => {
-            let n1 = char::from_digit(nibbles.1 as u32, 16).unwrap().to_string();
-            let n2 = char::from_digit(nibbles.2 as u32, 16).unwrap().to_string();
-            let n3 = char::from_digit(nibbles.3 as u32, 16).unwrap().to_string();
-            let nnn = u16::from_str_radix(&(n1 + &n2 + &n3), 16).unwrap();
-            *register_i = nnn;
-        },
-        0xB => {
-            let n1 = char::from_digit(nibbles.1 as u32, 16).unwrap().to_string();
-            let n2 = char::from_digit(nibbles.2 as u32, 16).unwrap().to_string();
-            let n3 = char::from_digit(nibbles.3 as u32, 16).unwrap().to_string();
-            let nnn = u16::from_str_radix(&(n1 + &n2 + &n3), 16).unwrap();
-            jump_v0_instruction(*registers, program_counter, nnn);
-        },
-        0xC => {
-            let n1 = char::from_digit(nibbles.2 as u32, 16).unwrap().to_string();
-            let n2 = char::from_digit(nibbles.3 as u32, 16).unwrap().to_string();
-            let nn = u8::from_str_radix(&(n1 + &n2), 16).unwrap();
-            rand_instruction(registers, nibbles.1.into(), nn);
-        },
-        0xD => {
-            let screen_width = 64;
-            let screen_height = 32;
-
-            let x_coordinate: u8 = *registers.get::<usize>(nibbles.1.into()).expect("NON VALID REGISTER");
-            let y_coordinate: u8 = *registers.get::<usize>(nibbles.2.into()).expect("NON VALID REGISTER");
-            registers[0xF as usize] = 0;
-            let n: u8 = nibbles.3;
-
-            let x_mod: u8 = x_coordinate % screen_width;
-            let y_mod: u8 = y_coordinate % screen_height;
-
-            for i in 0..n {
-                let sprite_row  = memory[(*register_i + u16::from(i)) as usize] as u8;
-
-                for j in (0..8).rev() {
-                    let current_bit = (sprite_row >> j) & 1;
-                    
-                    let x_coord: u8 = (x_mod + (7 - j)) % screen_width;
-                    let y_coord: u8 = (y_mod + i) % screen_height;
-
-                    if current_bit == 1 {
-
-                        let current_pixel = screen.get_pixel(&x_coord, &y_coord).expect("COORDINATES OUT OF BOUND");
-                        match current_pixel {
-                            PixelState::Off => screen.set_pixel(&x_coord, &y_coord, PixelState::On),
-                            PixelState::On => {
-                                screen.set_pixel(&x_coord, &y_coord, PixelState::Off);
-                                registers[0xF as usize] = 1;
-                            },
-                        }
-                    }
-                }
-            }
-            execute!(std::io::stdout(), Clear(ClearType::All)).expect("ERROR CLEARING THE SCREEN");
-            screen.display_pixels();
-        },
-        0xE => match nibbles.2 {
-            0x9 => {
-                let index = nibbles.1.into();
-                if keypad.is_pressed(index) {
-                    *program_counter += 2;
-                }
-            },
-            0xA => {
-                let index = nibbles.1.into();
-                if !keypad.is_pressed(index) {
-                    *program_counter += 2;
-                }
+            "--color" => match args.next().and_then(|color| parse_pixel_color(color)) {
+                Some((index, rgb)) => options.palette.colors[index] = rgb,
+                _ => return Err(format!("--color needs a pixel value and a colour, e.g. 2=#FF8800\n{}", USAGE)),
             },
-            _ => {
-                println!("{:?}, {:?}", nibbles, instruction);
-                panic!("NON VALID INSTRUCTION");
+            "--screenshot-scale" => match args.next().and_then(|scale| scale.parse::<usize>().ok()) {
+                Some(scale) if (1..=MAX_SCALE).contains(&scale) => options.screenshot_scale = Some(scale),
+                _ => return Err(format!("--screenshot-scale needs a whole number from 1 to {}\n{}", MAX_SCALE, USAGE)),
             },
-        },
-        0xF => match nibbles.2 {
-            0x0 => match nibbles.3 {
-                0x0 => {
-                    registers[nibbles.1 as usize] = 0;
-                },
-                0x7 => {
-                    let delay_timer = delay_timer_arc.lock().expect("Failed to lock delay timer");
-                    registers[nibbles.1 as usize] = delay_timer.get_timer();
-                    drop(delay_timer);
+            "--screenshot-after-frames" => match (args.next().and_then(|frames| frames.parse::<u64>().ok()), args.next()) {
+                (Some(frames), Some(path)) if ImageFormat::from_path(Path::new(path)).is_some() => {
+                    options.screenshot_after = Some((frames, PathBuf::from(path)));
                 },
-                0xA => {
-                    loop {
-                        let mut input = [0u8; 1];
-                        match std::io::stdin().read_exact(&mut input) {
-                            Ok(_) => {
-                                let key: u8 = match input[0] {
-                                    b'1' => 0x1,
-                                    b'2' => 0x2,
-                                    b'3' => 0x3,
-                                    b'4' => 0xC,
-                                    b'q' => 0x4,
-                                    b'w' => 0x5,
-                                    b'e' => 0x6,
-                                    b'r' => 0xD,
-                                    b'a' => 0x7,
-                                    b's' => 0x8,
-                                    b'd' => 0x9,
-                                    b'f' => 0xE,
-                                    b'z' => 0xA,
-                                    b'x' => 0x0,
-                                    b'c' => 0xB,
-                                    b'v' => 0xF,
-                                    _ => continue, // ignore other keys
-                                };
-                                registers[nibbles.1 as usize] = key;
-                                keypad.press_key(key.into());
-                                for i in 0..16 {
-                                    if i == key {
-                                        continue;
-                                    }
-                                    keypad.release_key(i.into());
-                                }
-                                break;
-                            }
-                            Err(_) => continue, // ignore errors
-                        }
-                    }
-                },
-                _ => {
-                    println!("{:?}, {:?}", nibbles, instruction);
-                    panic!("NON VALID INSTRUCTION");
-                },
-            },
-            0x1 => match nibbles.3 {
-                0x5 => {
-                    let mut delay_timer = delay_timer_arc.lock().expect("Failed to lock delay timer");
-                    delay_timer.set_timer(*registers.get::<usize>(nibbles.1.into()).expect("NON VALID REGISTER"));
-                    drop(delay_timer);
-                },
-                0x8 => {
-                    let mut sound_timer = sound_timer_arc.lock().expect("Failed to lock delay timer");
-                    sound_timer.set_timer(*registers.get::<usize>(nibbles.1.into()).expect("NON VALID REGISTER"));
-                    drop(sound_timer);
-                },
-                0xE => {
-                    let (result, _overflow) = register_i.overflowing_add((*registers.get::<usize>(nibbles.1.into())
-                                                        .expect("NON VALID REGISTER")).into());
-                    *register_i = result;
-                },
-                _ => {
-                    println!("{:?}, {:?}", nibbles, instruction);
-                    panic!("NON VALID INSTRUCTION");
-                },
-            },
-            0x2 => {
-                let address: u16 = FromPrimitive::from_u8(*registers.get::<usize>(nibbles.1 as usize).expect("NON VALID REGISTER")).unwrap();
-                *register_i = address * 5; 
-            },
-            0x3 => {
-                let value: i32 = i32::from(*registers.get::<usize>(nibbles.1.into()).expect("NON VALID REGISTER")); 
-                memory[(*register_i) as usize] = (value / 100) as u8;
-                memory[(*register_i + 1) as usize] = (value / 10 % 10) as u8;
-                memory[(*register_i + 2) as usize] = (value % 10) as u8; 
+                _ => return Err(format!("--screenshot-after-frames needs a number of frames and a .png, .ppm or .pbm file\n{}", USAGE)),
             },
-            0x5=> {
-                for i in 0..=nibbles.1 {
-                    let memory_index: usize = (*register_i + u16::from(i)) as usize;
-                    memory[memory_index] = *registers.get::<usize>(i as usize).expect("NON VALID REGISTER");
-                }
-            },
-            0x6 => {
-                let mut index: usize = (*register_i) as usize;
-                for i in 0..=nibbles.1 {
-                    let current_value = memory[index];
-                    registers[i as usize] = current_value;
-                    index += 1;
-                }
-            },
-            _ => {
-                println!("{:?}, {:?}", nibbles, instruction);
-                panic!("NON VALID INSTRUCTION");
-            }, 
-        },
-        _ => {
-            println!("{:?}, {:?}", nibbles, instruction);
-            panic!("NON VALID INSTRUCTION");
-        },
-    }
-}
-
-fn load_file_to_memory(memory: &mut [u8], file_path: &str, start_address: usize) -> Result<()> {
-    let mut file = File::open(file_path)?;
-    let mut buffer = [0; 2];
-    let mut address = start_address;
-
-    loop {
-        match file.read_exact(&mut buffer) {
-            Ok(_) => {
-                let opcode = u16::from_be_bytes(buffer);
-                memory[address] = ((opcode >> 8) & 0xFF) as u8;
-                memory[address + 1] = (opcode & 0xFF) as u8;
-
-                address += 2;
-            }
-            Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => break,
-            Err(e) => return Err(e),
+            option if option.starts_with("--") => return Err(format!("unknown option {}\n{}", option, USAGE)),
+            _ => positional.push(arg.clone()),
         }
     }
-
-    Ok(())
+    Ok((options, positional))
 }
 
 fn get_path_from_user() -> PathBuf {
@@ -383,76 +193,350 @@ fn get_path_from_user() -> PathBuf {
     let path = std::path::Path::new(&file_path).to_owned();
 
     // Check if the file exists using the `fs` module
-    if path.exists() && path.is_file() {
-    } else {
+    if !(path.exists() && path.is_file()) {
         panic!("THE FILE IS NOT FOUND");
     }
     path
 }
 
-fn main() {
-    let mut registers: [u8; 16] = [0u8; 16]; // registers v0 - vf 
-    let mut stack: Stack<u16> = Stack::new(); // stack of addresses
-    let mut screen: Screen = Screen::new(); // set the screen pixels to all off  
-    let mut memory: [u8; 4096] = [0; 4096]; // set the memory
-    let mut keypad: Keypad = Keypad::new();
-    let mut program_counter: usize = 0x200;
-
-    let delay_timer = DelayTimer::new();
-    let sound_timer = SoundTimer::new();
-
-    let delay_timer_arc = Arc::new(Mutex::new(Box::new(delay_timer) as Box<dyn Timer + Send>));
-    let sound_timer_arc = Arc::new(Mutex::new(Box::new(sound_timer) as Box<dyn Timer + Send>));
-
-    let delay_timer_arc_clone = delay_timer_arc.clone();
-    let sound_timer_arc_clone = sound_timer_arc.clone();
-
-    thread::spawn(move || decrement_timer(delay_timer_arc_clone));
-    thread::spawn(move || decrement_timer(sound_timer_arc_clone));
-
-    let font: [u8; 80] = [0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-                          0x20, 0x60, 0x20, 0x20, 0x70, // 1
-                          0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-                          0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-                          0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-                          0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-                          0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-                          0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-                          0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-                          0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-                          0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-                          0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-                          0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-                          0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-                          0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-                          0xF0, 0x80, 0xF0, 0x80, 0x80]; // F
-
-    // setting the font, and pointing I to it
-    for (i ,font_byte) in font.iter().enumerate() {
-        memory[i as usize + 0x50 as usize] = *font_byte;
+fn exit_with_error<E: std::fmt::Display>(error: E) -> ! {
+    terminal::restore();
+    eprintln!("{}", error);
+    process::exit(1);
+}
+
+// plays the sound timer on the audio device and records it to a WAV file, without a device
+// the terminal bell rings whenever a beep starts, runs that aren't `live` are only recorded
+struct AudioOutput {
+    renderer: AudioRenderer,
+    wav: Option<WavWriter<BufWriter<File>>>,
+    #[cfg(feature = "sound")]
+    device: Option<AudioDevice>,
+    live: bool,
+    was_playing: bool,
+    samples: Vec<i16>,
+}
+
+impl AudioOutput {
+    fn new(options: &Options, live: bool) -> io::Result<AudioOutput> {
+        #[cfg(feature = "sound")]
+        let device = if !live {
+            None
+        } else {
+            match AudioDevice::open() {
+                Ok(device) => Some(device),
+                Err(error) => {
+                    eprintln!("{}, FALLING BACK TO THE TERMINAL BELL", error);
+                    None
+                },
+            }
+        };
+        #[cfg(feature = "sound")]
+        let sample_rate = device.as_ref().map_or(DEFAULT_SAMPLE_RATE, AudioDevice::sample_rate);
+        #[cfg(not(feature = "sound"))]
+        let sample_rate = DEFAULT_SAMPLE_RATE;
+        // the bell has no pitch or volume, so the beeper options would go unheard
+        #[cfg(not(feature = "sound"))]
+        if live && options.audio_out.is_none() && options.beeper != Beeper::default() {
+            eprintln!("--beep-frequency and --volume only change --audio-out in builds without the sound feature");
+        }
+
+        let wav = match &options.audio_out {
+            Some(path) => Some(WavWriter::new(BufWriter::new(File::create(path)?), sample_rate)?),
+            None => None,
+        };
+        Ok(AudioOutput {
+            renderer: AudioRenderer::new(sample_rate, options.beeper),
+            wav,
+            #[cfg(feature = "sound")]
+            device,
+            live,
+            was_playing: false,
+            samples: Vec::new(),
+        })
+    }
+
+    fn has_device(&self) -> bool {
+        #[cfg(feature = "sound")]
+        return self.device.is_some();
+        #[cfg(not(feature = "sound"))]
+        return false;
+    }
+
+    // produces the sound of the time that passed since the last call
+    fn update(&mut self, chip8: &Chip8, elapsed: Duration) -> io::Result<()> {
+        let playing = chip8.sound_timer() > 0;
+        if playing && !self.was_playing && self.live && !self.has_device() {
+            print!("\x07");
+        }
+        self.was_playing = playing;
+
+        let count = (f64::from(self.renderer.sample_rate()) * elapsed.as_secs_f64()).round() as usize;
+        self.samples.clear();
+        self.renderer.render(chip8, count, &mut self.samples);
+        if let Some(wav) = &mut self.wav {
+            wav.write_samples(&self.samples)?;
+        }
+        #[cfg(feature = "sound")]
+        if let Some(device) = &self.device {
+            device.push(&self.samples);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> io::Result<()> {
+        if let Some(wav) = self.wav {
+            wav.finish()?;
+        }
+        Ok(())
+    }
+}
+
+fn report_speed(scheduler: &Scheduler, elapsed: Duration) {
+    let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+    eprintln!("{} frames, {} instructions in {:.3}s: {:.0} frames per second, {:.0} instructions per second",
+              scheduler.frames(), scheduler.instructions(), seconds,
+              scheduler.frames() as f64 / seconds, scheduler.instructions() as f64 / seconds);
+}
+
+// the hotkeys work on `game.state` next to `game.ch8`, a failure is reported without stopping the game
+fn save_state_file(chip8: &Chip8, state_path: &Path, display: &Display) {
+    match fs::write(state_path, chip8.save_state()) {
+        Ok(()) => display.show_status(chip8, &format!("saved {}", state_path.display())),
+        Err(error) => display.show_status(chip8, &format!("FAILED TO SAVE STATE: {}", error)),
+    }
+}
+
+fn load_state_file(chip8: &mut Chip8, state_path: &Path, display: &mut Display) {
+    match fs::read(state_path).map_err(Into::into).and_then(|state| chip8.load_state(&state)) {
+        Ok(()) => {
+            display.render(chip8);
+            display.show_status(chip8, &format!("loaded {}", state_path.display()));
+        },
+        Err(error) => display.show_status(chip8, &format!("{}: {}", state_path.display(), error)),
     }
+}
 
-    let mut register_i  = 0x50; // register 16 = i
+// `game-1.png`, `game-2.png`, ... next to `game.ch8`, so no screenshot overwrites an earlier one
+fn next_screenshot_path(rom: &Path) -> PathBuf {
+    let stem = rom.file_stem().unwrap_or_default().to_string_lossy();
+    (1u32..)
+        .map(|number| rom.with_file_name(format!("{}-{}.png", stem, number)))
+        .find(|path| !path.exists())
+        .expect("NO FREE SCREENSHOT NAME")
+}
 
-    let path = get_path_from_user();
-    
-    match load_file_to_memory(&mut memory, path.to_str().expect("COULDN'T CONVERET PATH TO &str"), 0x200){
-        Ok(_) => {},
-        Err(_) => panic!("FAILED TO LOAD ROM TO MEMORY"),
+fn save_screenshot_file(chip8: &Chip8, path: &Path, options: &Options, display: &Display) {
+    let scale = options.screenshot_scale.unwrap_or(DEFAULT_SCALE);
+    match screenshot::save(chip8.screen(), &options.palette, scale, path) {
+        Ok(()) => display.show_status(chip8, &format!("saved {}", path.display())),
+        Err(error) => display.show_status(chip8, &format!("FAILED TO SAVE SCREENSHOT: {}", error)),
     }
+}
 
-    loop {
-        let current_instruction: u16 = (u16::from(memory[program_counter]) << 8) | u16::from(memory[program_counter + 1]);
-        if (program_counter >= memory.len()) || (current_instruction == 65535) {
-            break;
+fn run(path: &Path, options: &Options) {
+    let mut chip8 = Chip8::new();
+    chip8.set_platform(options.platform);
+    chip8.set_quirks(options.quirks);
+    if let Err(error) = chip8.set_flags_file(path.with_extension("flags")) {
+        exit_with_error(format!("FAILED TO READ THE RPL FLAGS: {}", error));
+    }
+
+    // Octo sources are compiled on the fly
+    let loaded = if is_octo_source(path) {
+        chip8.load_program(&build(path, octo::compile))
+    } else {
+        chip8.load_rom(path)
+    };
+    if let Err(error) = loaded {
+        exit_with_error(format!("FAILED TO LOAD ROM: {}", error));
+    }
+
+    // the state replaces the whole machine, the ROM only has to match it
+    if let Some(state_path) = &options.load_state {
+        let loaded = fs::read(state_path).map_err(Into::into).and_then(|state| chip8.load_state(&state));
+        if let Err(error) = loaded {
+            exit_with_error(format!("{}: {}", state_path.display(), error));
         }
-        execute_instruction(&mut registers, &mut register_i, &mut screen, &mut keypad,
-                            &mut memory, &mut stack, current_instruction,
-                            &mut program_counter, &delay_timer_arc,
-                            &sound_timer_arc);
-        
-        // get::<usize> the next instruction from memory
-        program_counter += 2;
-        thread::sleep(Duration::from_millis(100));
     }
-}
\ No newline at end of file
+
+    let instructions_per_frame = options.instructions_per_frame.unwrap_or(options.platform.instructions_per_frame());
+    if options.debug {
+        let mut debugger = Debugger::new(instructions_per_frame);
+        debugger.set_palette(options.palette);
+        debugger.set_screenshot_scale(options.screenshot_scale.unwrap_or(DEFAULT_SCALE));
+        // Ctrl-C stops `continue`, a second one while nothing runs ends the session as usual
+        let interrupted = debugger.interrupt_flag();
+        let registered = flag::register_conditional_default(SIGINT, Arc::clone(&interrupted))
+            .and_then(|_| flag::register(SIGINT, interrupted));
+        if let Err(error) = registered {
+            exit_with_error(format!("FAILED TO CATCH CTRL-C: {}", error));
+        }
+        if let Err(error) = debugger.run(&mut chip8, &mut io::stdin().lock(), &mut io::stdout()) {
+            exit_with_error(error);
+        }
+        return;
+    }
+
+    // a screenshot run draws nothing, plays nothing and goes as fast as it can
+    let headless = options.screenshot_after.is_some();
+    let mut audio = match AudioOutput::new(options, !headless) {
+        Ok(audio) => audio,
+        Err(error) => exit_with_error(format!("FAILED TO OPEN THE AUDIO OUTPUT: {}", error)),
+    };
+    let mut scheduler = Scheduler::new(instructions_per_frame);
+    scheduler.set_pacing(if headless { Pacing::Uncapped } else { options.pacing });
+    let frame_limit = match &options.screenshot_after {
+        Some((frames, _)) => Some(options.frames.map_or(*frames, |limit| limit.min(*frames))),
+        None => options.frames,
+    };
+    // without a terminal, e.g. with input redirected, the program runs without a keyboard
+    let keymap = match KeyMap::load(Some(path)) {
+        Ok(keymap) => keymap,
+        Err(error) => exit_with_error(error),
+    };
+    let mut terminal = if headless { None } else { Terminal::enter(keymap).ok() };
+    let renderer_name = if headless { "headless" } else { options.renderer.as_deref().unwrap_or(RENDERERS[0]) };
+    let renderer = renderer::from_name(renderer_name, options.palette, ColorDepth::detect()).expect("UNKNOWN RENDERER");
+    let mut display = Display::new(renderer);
+    let mut history = RewindBuffer::default();
+    let state_path = path.with_extension("state");
+    let started = Instant::now();
+    let result = 'frames: loop {
+        if let Some(terminal) = &mut terminal {
+            let hotkeys = match terminal.poll(chip8.keypad_mut()) {
+                Ok(hotkeys) => hotkeys,
+                Err(error) => exit_with_error(format!("FAILED TO READ THE KEYBOARD: {}", error)),
+            };
+            for hotkey in hotkeys {
+                match hotkey {
+                    Hotkey::Quit => break 'frames Ok(()),
+                    Hotkey::SaveState => save_state_file(&chip8, &state_path, &display),
+                    Hotkey::LoadState => {
+                        load_state_file(&mut chip8, &state_path, &mut display);
+                        terminal.restore_keys(chip8.keypad_mut());
+                    },
+                    Hotkey::Screenshot => save_screenshot_file(&chip8, &next_screenshot_path(path), options, &display),
+                }
+            }
+            // holding the rewind key plays the recorded frames backwards
+            if terminal.is_rewinding() {
+                match history.rewind(&mut chip8) {
+                    Ok(true) => {
+                        terminal.restore_keys(chip8.keypad_mut());
+                        display.render(&chip8);
+                    },
+                    Ok(false) => {},
+                    Err(error) => display.show_status(&chip8, &format!("can't rewind: {}", error)),
+                }
+                scheduler.wait_for_next_frame();
+                continue;
+            }
+        }
+        history.record(&mut chip8);
+
+        let running = match scheduler.run_frame(&mut chip8) {
+            Ok(running) => running,
+            Err(error) => break Err(error),
+        };
+        // drawing once per frame instead of after every DXYN keeps the display from flickering
+        display.render(&chip8);
+        // the last frame still sounds, a program may end on a beep
+        if let Err(error) = audio.update(&chip8, FRAME_DURATION) {
+            exit_with_error(format!("FAILED TO WRITE THE AUDIO: {}", error));
+        }
+        if !running {
+            break Ok(());
+        }
+        if frame_limit.is_some_and(|frames| scheduler.frames() >= frames) {
+            break Ok(());
+        }
+        scheduler.wait_for_next_frame();
+    };
+    drop(terminal);
+    if options.pacing == Pacing::Uncapped {
+        report_speed(&scheduler, started.elapsed());
+    }
+    // the WAV header is only complete once finished, so do it before reporting errors
+    if let Err(error) = audio.finish() {
+        exit_with_error(format!("FAILED TO WRITE THE AUDIO: {}", error));
+    }
+    // a program that ends early or fails is captured as it stopped
+    if let Some((_, screenshot_path)) = &options.screenshot_after {
+        let scale = options.screenshot_scale.unwrap_or(DEFAULT_SCALE);
+        if let Err(error) = screenshot::save(chip8.screen(), &options.palette, scale, screenshot_path) {
+            exit_with_error(format!("{}: FAILED TO SAVE SCREENSHOT: {}", screenshot_path.display(), error));
+        }
+    }
+    if let Err(error) = result {
+        exit_with_error(error);
+    }
+}
+
+fn disasm(path: &Path) {
+    // big enough for XO-CHIP programs
+    let mut memory = vec![0u8; XO_MEMORY_SIZE];
+    let length = match load_file_to_memory(&mut memory, path, PROGRAM_START) {
+        Ok(length) => length,
+        Err(error) => exit_with_error(format!("FAILED TO LOAD ROM: {}", error)),
+    };
+    print!("{}", disassemble(&memory[PROGRAM_START..PROGRAM_START + length]));
+}
+
+fn build<F>(source_path: &Path, compile: F) -> Vec<u8>
+    where F: Fn(&str) -> Result<Vec<u8>, AssembleError>
+{
+    let source = match fs::read_to_string(source_path) {
+        Ok(source) => source,
+        Err(error) => exit_with_error(format!("FAILED TO READ SOURCE: {}", error)),
+    };
+    match compile(&source) {
+        Ok(rom) => rom,
+        Err(error) => exit_with_error(format!("{}: {}", source_path.display(), error)),
+    }
+}
+
+fn is_octo_source(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "8o")
+}
+
+fn asm<F>(source_path: &Path, output_path: &Path, compile: F)
+    where F: Fn(&str) -> Result<Vec<u8>, AssembleError>
+{
+    let rom = build(source_path, compile);
+    if let Err(error) = fs::write(output_path, rom) {
+        exit_with_error(format!("FAILED TO WRITE ROM: {}", error));
+    }
+}
+
+// shows the layout a ROM would be played with, the global key map when no ROM is given
+fn print_keymap(rom: Option<&Path>) {
+    match KeyMap::load(rom) {
+        Ok(keymap) => print!("{}", keymap),
+        Err(error) => exit_with_error(error),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (options, args) = match parse_arguments(&args) {
+        Ok(parsed) => parsed,
+        Err(error) => exit_with_error(error),
+    };
+    if options.print_keymap {
+        match args.as_slice() {
+            [] => print_keymap(None),
+            [rom] => print_keymap(Some(Path::new(rom))),
+            _ => exit_with_error(USAGE),
+        }
+        return;
+    }
+    match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+        [] => run(&get_path_from_user(), &options),
+        ["disasm", rom] => disasm(Path::new(rom)),
+        ["asm", source, output] => asm(Path::new(source), Path::new(output), assemble),
+        ["octo", source, output] => asm(Path::new(source), Path::new(output), octo::compile),
+        [rom] if !["disasm", "asm", "octo"].contains(rom) => run(Path::new(rom), &options),
+        _ => exit_with_error(USAGE),
+    }
+}
@@ -0,0 +1,932 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::assembler::AssembleError;
+use crate::chip8::{PROGRAM_START, XO_MEMORY_SIZE};
+use crate::instruction::Instruction;
+
+// how many macro expansions a whole program may have, counted across the program rather than
+// by nesting depth, a macro that expands itself forever runs into this instead of hanging
+const MAX_TOTAL_MACRO_EXPANSIONS: usize = 100_000;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    arguments: Vec<String>,
+    body: Vec<Token>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FixupKind {
+    Address,         // the low 12 bits of the opcode at the address
+    UnpackHigh(u8),  // the byte at the address, a nibble followed by the top 4 bits of the address
+    UnpackLow,       // the byte at the address, the low byte of the address
+    Long,            // the operand of the F000 NNNN at the address, all 16 bits
+}
+
+#[derive(Debug, Clone)]
+struct Fixup {
+    address: usize,
+    label: String,
+    kind: FixupKind,
+    line: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Register(usize),
+    Byte(u8),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Condition {
+    Equal(usize, Operand),
+    NotEqual(usize, Operand),
+    Key(usize),
+    NotKey(usize),
+}
+
+impl Condition {
+    fn negate(self) -> Condition {
+        match self {
+            Condition::Equal(x, operand) => Condition::NotEqual(x, operand),
+            Condition::NotEqual(x, operand) => Condition::Equal(x, operand),
+            Condition::Key(x) => Condition::NotKey(x),
+            Condition::NotKey(x) => Condition::Key(x),
+        }
+    }
+
+    // the instruction that skips the next one when the condition doesn't hold
+    fn skip_unless(self) -> Instruction {
+        match self {
+            Condition::Equal(x, Operand::Byte(nn)) => Instruction::SneByte(x, nn),
+            Condition::Equal(x, Operand::Register(y)) => Instruction::SneReg(x, y),
+            Condition::NotEqual(x, Operand::Byte(nn)) => Instruction::SeByte(x, nn),
+            Condition::NotEqual(x, Operand::Register(y)) => Instruction::SeReg(x, y),
+            Condition::Key(x) => Instruction::Sknp(x),
+            Condition::NotKey(x) => Instruction::Skp(x),
+        }
+    }
+}
+
+struct LoopFrame {
+    start: usize,
+    breaks: Vec<usize>,
+}
+
+struct Compiler {
+    tokens: VecDeque<Token>,
+    rom: Vec<u8>,
+    here: usize,
+    line: usize,
+    started: bool,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, usize>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    loops: Vec<LoopFrame>,
+    branches: Vec<usize>, // jumps of open `begin`/`else` blocks waiting for their target
+    next_label: Option<String>,
+    expansions: usize,
+}
+
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (index, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or("");
+        for word in code.split_whitespace() {
+            // braces and parentheses don't need whitespace around them
+            let mut start = 0;
+            for (position, c) in word.char_indices() {
+                if "{}()".contains(c) {
+                    if position > start {
+                        tokens.push_back(Token { text: word[start..position].to_string(), line: index + 1 });
+                    }
+                    tokens.push_back(Token { text: c.to_string(), line: index + 1 });
+                    start = position + 1;
+                }
+            }
+            if start < word.len() {
+                tokens.push_back(Token { text: word[start..].to_string(), line: index + 1 });
+            }
+        }
+    }
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()? as f64
+    } else if let Some(binary) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i64::from_str_radix(binary, 2).ok()? as f64
+    } else if digits.chars().next()?.is_ascii_digit() {
+        digits.parse::<f64>().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<usize> {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some('v') | Some('V') if text.len() == 2 => chars.next()?.to_digit(16).map(|x| x as usize),
+        _ => None,
+    }
+}
+
+fn is_reserved(name: &str) -> bool {
+    parse_register(name).is_some() || matches!(name,
+        ":=" | "+=" | "-=" | "=-" | "|=" | "&=" | "^=" | ">>=" | "<<=" | "==" | "!=" | "<" | ">" | "<=" | ">=" |
+        "key" | "-key" | "hex" | "random" | "delay" | "buzzer" | "i" | "then" | "begin" | "else" | "end" |
+        "loop" | "again" | "while" | "if" | "return" | "clear" | "bcd" | "save" | "load" | "sprite" |
+        "jump" | "jump0" | "native" | ";" | "bighex" | "hires" | "lores" | "scroll-down" | "scroll-left" |
+        "scroll-right" | "exit" | "saveflags" | "loadflags" | "plane" | "scroll-up" | "long" |
+        "audio" | "pitch")
+}
+
+impl Compiler {
+    fn new(source: &str) -> Compiler {
+        Compiler {
+            tokens: tokenize(source),
+            rom: Vec::new(),
+            here: PROGRAM_START,
+            line: 1,
+            started: false,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            loops: Vec::new(),
+            branches: Vec::new(),
+            next_label: None,
+            expansions: 0,
+        }
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, AssembleError> {
+        Err(AssembleError { line: self.line, message })
+    }
+
+    fn next_token(&mut self) -> Result<String, AssembleError> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.line = token.line;
+                Ok(token.text)
+            },
+            None => self.error("UNEXPECTED END OF FILE".to_string()),
+        }
+    }
+
+    fn peek_token(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), AssembleError> {
+        let token = self.next_token()?;
+        if token != expected {
+            return self.error(format!("EXPECTED '{}' BUT FOUND '{}'", expected, token));
+        }
+        Ok(())
+    }
+
+    fn name(&mut self) -> Result<String, AssembleError> {
+        let name = self.next_token()?;
+        if is_reserved(&name) || parse_number(&name).is_some() || name.starts_with(':') {
+            return self.error(format!("'{}' CAN'T BE USED AS A NAME", name));
+        }
+        Ok(name)
+    }
+
+    // programs start at `main`, which needs a jump at 0x200 unless `main` is the very first label
+    fn start(&mut self, label: Option<&str>) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+        if label != Some("main") {
+            self.fixups.push(Fixup { address: self.here, label: "main".to_string(), kind: FixupKind::Address, line: self.line });
+            self.emit_word(Instruction::Jp(0).encode());
+        }
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        self.start(None);
+        if let Some(label) = self.next_label.take() {
+            // `:next` points at the immediate byte of the following instruction
+            self.labels.insert(label, self.here + 1);
+        }
+        let offset = self.here - PROGRAM_START;
+        if self.rom.len() <= offset {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.here += 1;
+    }
+
+    fn emit_word(&mut self, word: u16) {
+        let [high, low] = word.to_be_bytes();
+        self.emit_byte(high);
+        self.emit_byte(low);
+    }
+
+    fn instruction(&mut self, instruction: Instruction) {
+        for byte in instruction.to_bytes() {
+            self.emit_byte(byte);
+        }
+    }
+
+    // emits an instruction whose address is a label, which may not be defined yet
+    fn instruction_to_label(&mut self, instruction: Instruction, label: String) {
+        self.start(None);
+        self.fixups.push(Fixup { address: self.here, label, kind: FixupKind::Address, line: self.line });
+        self.instruction(instruction);
+    }
+
+    fn define_label(&mut self, name: String) -> Result<(), AssembleError> {
+        self.start(Some(&name));
+        if self.labels.contains_key(&name) {
+            return self.error(format!("LABEL '{}' IS DEFINED TWICE", name));
+        }
+        self.labels.insert(name, self.here);
+        Ok(())
+    }
+
+    // the `- vy` of `save vx - vy` and `load vx - vy`
+    fn register_range_end(&mut self) -> Result<Option<usize>, AssembleError> {
+        if self.peek_token() != Some("-") {
+            return Ok(None);
+        }
+        self.next_token()?;
+        Ok(Some(self.register()?))
+    }
+
+    fn register(&mut self) -> Result<usize, AssembleError> {
+        let token = self.next_token()?;
+        self.register_from(&token)
+    }
+
+    fn register_from(&self, token: &str) -> Result<usize, AssembleError> {
+        match parse_register(token).or_else(|| self.aliases.get(token).copied()) {
+            Some(x) => Ok(x),
+            None => self.error(format!("EXPECTED A REGISTER BUT FOUND '{}'", token)),
+        }
+    }
+
+    fn is_register(&self, token: &str) -> bool {
+        parse_register(token).is_some() || self.aliases.contains_key(token)
+    }
+
+    // a number, constant or `{ expression }`
+    fn value(&mut self) -> Result<f64, AssembleError> {
+        let token = self.next_token()?;
+        if token == "{" {
+            let value = self.expression()?;
+            self.expect("}")?;
+            return Ok(value);
+        }
+        self.value_of(&token)
+    }
+
+    fn value_of(&self, token: &str) -> Result<f64, AssembleError> {
+        if let Some(value) = parse_number(token) {
+            return Ok(value);
+        }
+        if let Some(value) = self.constants.get(token) {
+            return Ok(*value);
+        }
+        if let Some(address) = self.labels.get(token) {
+            return Ok(*address as f64);
+        }
+        match token {
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            _ => self.error(format!("UNDEFINED NAME '{}'", token)),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, AssembleError> {
+        let value = self.value()? as i64;
+        if !(-128..=0xFF).contains(&value) {
+            return self.error(format!("VALUE {} DOES NOT FIT IN A BYTE", value));
+        }
+        Ok(value as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AssembleError> {
+        let value = self.value()? as i64;
+        if !(0..=0xF).contains(&value) {
+            return self.error(format!("VALUE {} DOES NOT FIT IN A NIBBLE", value));
+        }
+        Ok(value as u8)
+    }
+
+    fn operand(&mut self) -> Result<Operand, AssembleError> {
+        if self.peek_token().is_some_and(|token| self.is_register(token)) {
+            return Ok(Operand::Register(self.register()?));
+        }
+        Ok(Operand::Byte(self.byte()?))
+    }
+
+    // jump targets and `i :=` take either a known address or a label defined later on
+    fn address_instruction<F: Fn(u16) -> Instruction>(&mut self, instruction: F) -> Result<(), AssembleError> {
+        let token = self.next_token()?;
+        if token != "{" && parse_number(&token).is_none() && !self.constants.contains_key(&token) && !self.labels.contains_key(&token) {
+            self.instruction_to_label(instruction(0), token);
+            return Ok(());
+        }
+        self.tokens.push_front(Token { text: token, line: self.line });
+        let address = self.value()? as i64;
+        if !(0..=0xFFF).contains(&address) {
+            return self.error(format!("ADDRESS {:#X} IS OUT OF RANGE", address));
+        }
+        self.instruction(instruction(address as u16));
+        Ok(())
+    }
+
+    // `i := long` reaches all of the XO-CHIP memory
+    fn long_address_instruction(&mut self) -> Result<(), AssembleError> {
+        let token = self.next_token()?;
+        if parse_number(&token).is_none() && !self.constants.contains_key(&token) && !self.labels.contains_key(&token) {
+            self.start(None);
+            self.fixups.push(Fixup { address: self.here, label: token, kind: FixupKind::Long, line: self.line });
+            self.instruction(Instruction::LdILong(0));
+            return Ok(());
+        }
+        self.tokens.push_front(Token { text: token, line: self.line });
+        let address = self.value()? as i64;
+        if !(0..=0xFFFF).contains(&address) {
+            return self.error(format!("ADDRESS {:#X} IS OUT OF RANGE", address));
+        }
+        self.instruction(Instruction::LdILong(address as u16));
+        Ok(())
+    }
+
+    // Octo expressions have no precedence, they are evaluated right to left
+    fn expression(&mut self) -> Result<f64, AssembleError> {
+        let left = self.expression_term()?;
+        let operator = match self.peek_token() {
+            Some(token) if is_binary_operator(token) => self.next_token()?,
+            _ => return Ok(left),
+        };
+        let right = self.expression()?;
+        Ok(match operator.as_str() {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" => left / right,
+            "%" => left % right,
+            "&" => ((left as i64) & (right as i64)) as f64,
+            "|" => ((left as i64) | (right as i64)) as f64,
+            "^" => ((left as i64) ^ (right as i64)) as f64,
+            "<<" => ((left as i64) << (right as i64)) as f64,
+            ">>" => ((left as i64) >> (right as i64)) as f64,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "<" => f64::from(u8::from(left < right)),
+            ">" => f64::from(u8::from(left > right)),
+            "<=" => f64::from(u8::from(left <= right)),
+            ">=" => f64::from(u8::from(left >= right)),
+            "==" => f64::from(u8::from(left == right)),
+            "!=" => f64::from(u8::from(left != right)),
+            _ => unreachable!(),
+        })
+    }
+
+    fn expression_term(&mut self) -> Result<f64, AssembleError> {
+        let token = self.next_token()?;
+        let value = match token.as_str() {
+            "(" => {
+                let value = self.expression()?;
+                self.expect(")")?;
+                value
+            },
+            "-" => -self.expression_term()?,
+            "~" => !(self.expression_term()? as i64) as f64,
+            "!" => f64::from(u8::from(self.expression_term()? == 0.0)),
+            "abs" => self.expression_term()?.abs(),
+            "sqrt" => self.expression_term()?.sqrt(),
+            "sin" => self.expression_term()?.sin(),
+            "cos" => self.expression_term()?.cos(),
+            "floor" => self.expression_term()?.floor(),
+            "ceil" => self.expression_term()?.ceil(),
+            "@" => {
+                let address = self.expression_term()? as usize;
+                match address.checked_sub(PROGRAM_START).and_then(|offset| self.rom.get(offset)) {
+                    Some(byte) => f64::from(*byte),
+                    None => 0.0,
+                }
+            },
+            _ => self.value_of(&token)?,
+        };
+        Ok(value)
+    }
+
+    fn condition(&mut self) -> Result<Condition, AssembleError> {
+        let x = self.register()?;
+        let operator = self.next_token()?;
+        let condition = match operator.as_str() {
+            "key" => Condition::Key(x),
+            "-key" => Condition::NotKey(x),
+            "==" => Condition::Equal(x, self.operand()?),
+            "!=" => Condition::NotEqual(x, self.operand()?),
+            "<" | ">=" | ">" | "<=" => {
+                // compare by subtracting into vf and looking at the borrow flag
+                let operand = self.operand()?;
+                let subtract_operand = operator == "<" || operator == ">=";
+                match (operand, subtract_operand) {
+                    (Operand::Register(y), true) => {
+                        self.instruction(Instruction::LdReg(0xF, x));
+                        self.instruction(Instruction::Sub(0xF, y));
+                    },
+                    (Operand::Register(y), false) => {
+                        self.instruction(Instruction::LdReg(0xF, y));
+                        self.instruction(Instruction::Sub(0xF, x));
+                    },
+                    (Operand::Byte(nn), true) => {
+                        self.instruction(Instruction::Ld(0xF, nn));
+                        self.instruction(Instruction::Subn(0xF, x));
+                    },
+                    (Operand::Byte(nn), false) => {
+                        self.instruction(Instruction::Ld(0xF, nn));
+                        self.instruction(Instruction::Sub(0xF, x));
+                    },
+                }
+                // vf is 1 when there was no borrow
+                let no_borrow = operator == ">=" || operator == "<=";
+                Condition::Equal(0xF, Operand::Byte(u8::from(no_borrow)))
+            },
+            _ => return self.error(format!("UNKNOWN COMPARISON '{}'", operator)),
+        };
+        Ok(condition)
+    }
+
+    // the instruction that skips the jump out of a block when the condition holds
+    fn conditional_jump(&mut self, condition: Condition) -> usize {
+        self.instruction(condition.negate().skip_unless());
+        let jump = self.here;
+        self.instruction(Instruction::Jp(0));
+        jump
+    }
+
+    fn patch_jump(&mut self, jump: usize, target: usize) {
+        let offset = jump - PROGRAM_START;
+        let opcode = u16::from_be_bytes([self.rom[offset], self.rom[offset + 1]]);
+        let [high, low] = ((opcode & 0xF000) | (target as u16 & 0x0FFF)).to_be_bytes();
+        self.rom[offset] = high;
+        self.rom[offset + 1] = low;
+    }
+
+    fn register_statement(&mut self, x: usize) -> Result<(), AssembleError> {
+        let operator = self.next_token()?;
+        let rhs = self.next_token()?;
+        let instruction = match (operator.as_str(), rhs.as_str()) {
+            (":=", "key") => Instruction::LdKey(x),
+            (":=", "delay") => Instruction::LdDelay(x),
+            (":=", "random") => Instruction::Rnd(x, self.byte()?),
+            (_, _) if self.is_register(&rhs) => {
+                let y = self.register_from(&rhs)?;
+                match operator.as_str() {
+                    ":=" => Instruction::LdReg(x, y),
+                    "+=" => Instruction::AddReg(x, y),
+                    "-=" => Instruction::Sub(x, y),
+                    "=-" => Instruction::Subn(x, y),
+                    "|=" => Instruction::Or(x, y),
+                    "&=" => Instruction::And(x, y),
+                    "^=" => Instruction::Xor(x, y),
+                    ">>=" => Instruction::Shr(x, y),
+                    "<<=" => Instruction::Shl(x, y),
+                    _ => return self.error(format!("UNKNOWN OPERATOR '{}'", operator)),
+                }
+            },
+            (":=", _) | ("+=", _) | ("-=", _) => {
+                self.tokens.push_front(Token { text: rhs, line: self.line });
+                let nn = self.byte()?;
+                match operator.as_str() {
+                    ":=" => Instruction::Ld(x, nn),
+                    "+=" => Instruction::Add(x, nn),
+                    _ => Instruction::Add(x, nn.wrapping_neg()),
+                }
+            },
+            _ => return self.error(format!("NON VALID OPERANDS FOR '{}'", operator)),
+        };
+        self.instruction(instruction);
+        Ok(())
+    }
+
+    fn directive(&mut self, directive: &str) -> Result<(), AssembleError> {
+        match directive {
+            ":" => {
+                let name = self.name()?;
+                self.define_label(name)?;
+            },
+            ":alias" => {
+                let name = self.name()?;
+                let x = self.register()?;
+                self.aliases.insert(name, x);
+            },
+            ":const" | ":calc" => {
+                let name = self.name()?;
+                let value = self.value()?;
+                self.constants.insert(name, value);
+            },
+            ":byte" => {
+                let byte = self.byte()?;
+                self.emit_byte(byte);
+            },
+            ":org" => {
+                let address = self.value()? as usize;
+                if !(PROGRAM_START..XO_MEMORY_SIZE).contains(&address) {
+                    return self.error(format!(":org ADDRESS {:#X} IS OUT OF RANGE", address));
+                }
+                self.start(None);
+                self.here = address;
+            },
+            ":next" => {
+                let name = self.name()?;
+                self.next_label = Some(name);
+            },
+            ":unpack" => {
+                let nibble = self.nibble()?;
+                let label = self.next_token()?;
+                self.start(None);
+                self.fixups.push(Fixup { address: self.here + 1, label: label.clone(), kind: FixupKind::UnpackHigh(nibble), line: self.line });
+                self.instruction(Instruction::Ld(0x0, 0));
+                self.fixups.push(Fixup { address: self.here + 1, label, kind: FixupKind::UnpackLow, line: self.line });
+                self.instruction(Instruction::Ld(0x1, 0));
+            },
+            ":call" => {
+                self.address_instruction(Instruction::Call)?;
+            },
+            ":macro" => {
+                let name = self.name()?;
+                let mut arguments = Vec::new();
+                loop {
+                    let token = self.next_token()?;
+                    if token == "{" {
+                        break;
+                    }
+                    arguments.push(token);
+                }
+                let mut body = Vec::new();
+                let mut depth = 1;
+                loop {
+                    let token = self.tokens.pop_front().ok_or(AssembleError { line: self.line, message: "UNTERMINATED MACRO".to_string() })?;
+                    match token.text.as_str() {
+                        "{" => depth += 1,
+                        "}" => depth -= 1,
+                        _ => {},
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                    body.push(token);
+                }
+                self.macros.insert(name, Macro { arguments, body });
+            },
+            ":assert" => {
+                let value = self.value()?;
+                if value == 0.0 {
+                    return self.error("ASSERTION FAILED".to_string());
+                }
+            },
+            ":breakpoint" => {
+                self.name()?;
+            },
+            ":monitor" => {
+                self.next_token()?;
+                self.next_token()?;
+            },
+            _ => return self.error(format!("UNKNOWN DIRECTIVE '{}'", directive)),
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), AssembleError> {
+        let token = self.next_token()?;
+        if token.starts_with(':') {
+            return self.directive(&token);
+        }
+        if let Some(x) = parse_register(&token).or_else(|| self.aliases.get(&token).copied()) {
+            return self.register_statement(x);
+        }
+        if let Some(definition) = self.macros.get(&token).cloned() {
+            return self.expand_macro(definition);
+        }
+
+        match token.as_str() {
+            ";" | "return" => self.instruction(Instruction::Ret),
+            "clear" => self.instruction(Instruction::Cls),
+            "hires" => self.instruction(Instruction::HighRes),
+            "lores" => self.instruction(Instruction::LowRes),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.instruction(Instruction::ScrollDown(n));
+            },
+            "scroll-left" => self.instruction(Instruction::ScrollLeft),
+            "scroll-right" => self.instruction(Instruction::ScrollRight),
+            "exit" => self.instruction(Instruction::Exit),
+            "saveflags" => {
+                let x = self.register()?;
+                self.instruction(Instruction::StoreFlags(x));
+            },
+            "loadflags" => {
+                let x = self.register()?;
+                self.instruction(Instruction::LoadFlags(x));
+            },
+            "bcd" => {
+                let x = self.register()?;
+                self.instruction(Instruction::LdBcd(x));
+            },
+            "save" => {
+                let x = self.register()?;
+                match self.register_range_end()? {
+                    Some(y) => self.instruction(Instruction::StoreRange(x, y)),
+                    None => self.instruction(Instruction::StoreRegs(x)),
+                }
+            },
+            "load" => {
+                let x = self.register()?;
+                match self.register_range_end()? {
+                    Some(y) => self.instruction(Instruction::LoadRange(x, y)),
+                    None => self.instruction(Instruction::LoadRegs(x)),
+                }
+            },
+            "plane" => {
+                let n = self.value()? as i64;
+                if !(0..=3).contains(&n) {
+                    return self.error(format!("PLANE {} IS OUT OF RANGE", n));
+                }
+                self.instruction(Instruction::Plane(n as u8));
+            },
+            "audio" => self.instruction(Instruction::LdAudio),
+            "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.instruction(Instruction::LdPitch(x));
+            },
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.instruction(Instruction::ScrollUp(n));
+            },
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.instruction(Instruction::Drw(x, y, n));
+            },
+            "jump" => self.address_instruction(Instruction::Jp)?,
+            "jump0" => self.address_instruction(Instruction::JpV0)?,
+            "native" => {
+                // 0NNN, the emulator refuses to run it but the bytes are still emitted
+                let address = self.value()? as u16;
+                self.emit_word(address & 0x0FFF);
+            },
+            "delay" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.instruction(Instruction::SetDelay(x));
+            },
+            "buzzer" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.instruction(Instruction::SetSound(x));
+            },
+            "i" => {
+                let operator = self.next_token()?;
+                match operator.as_str() {
+                    "+=" => {
+                        let x = self.register()?;
+                        self.instruction(Instruction::AddI(x));
+                    },
+                    ":=" if self.peek_token() == Some("hex") => {
+                        self.next_token()?;
+                        let x = self.register()?;
+                        self.instruction(Instruction::LdFont(x));
+                    },
+                    ":=" if self.peek_token() == Some("long") => {
+                        self.next_token()?;
+                        self.long_address_instruction()?;
+                    },
+                    ":=" if self.peek_token() == Some("bighex") => {
+                        self.next_token()?;
+                        let x = self.register()?;
+                        self.instruction(Instruction::LdBigFont(x));
+                    },
+                    ":=" => self.address_instruction(Instruction::LdI)?,
+                    _ => return self.error(format!("UNKNOWN OPERATOR '{}' FOR I", operator)),
+                }
+            },
+            "if" => {
+                let condition = self.condition()?;
+                let block = self.next_token()?;
+                match block.as_str() {
+                    "then" => self.instruction(condition.skip_unless()),
+                    "begin" => {
+                        let jump = self.conditional_jump(condition);
+                        self.branches.push(jump);
+                    },
+                    _ => return self.error(format!("EXPECTED 'then' OR 'begin' BUT FOUND '{}'", block)),
+                }
+            },
+            "else" => {
+                let jump = match self.branches.pop() {
+                    Some(jump) => jump,
+                    None => return self.error("'else' WITHOUT 'begin'".to_string()),
+                };
+                let end_jump = self.here;
+                self.instruction(Instruction::Jp(0));
+                self.patch_jump(jump, self.here);
+                self.branches.push(end_jump);
+            },
+            "end" => {
+                let jump = match self.branches.pop() {
+                    Some(jump) => jump,
+                    None => return self.error("'end' WITHOUT 'begin'".to_string()),
+                };
+                self.patch_jump(jump, self.here);
+            },
+            "loop" => {
+                self.start(None);
+                self.loops.push(LoopFrame { start: self.here, breaks: Vec::new() });
+            },
+            "while" => {
+                if self.loops.is_empty() {
+                    return self.error("'while' OUTSIDE OF A LOOP".to_string());
+                }
+                let condition = self.condition()?;
+                let jump = self.conditional_jump(condition);
+                self.loops.last_mut().expect("CHECKED ABOVE").breaks.push(jump);
+            },
+            "again" => {
+                let frame = match self.loops.pop() {
+                    Some(frame) => frame,
+                    None => return self.error("'again' WITHOUT 'loop'".to_string()),
+                };
+                self.instruction(Instruction::Jp(frame.start as u16));
+                for jump in frame.breaks {
+                    self.patch_jump(jump, self.here);
+                }
+            },
+            _ => {
+                // raw data bytes, or a call to a subroutine
+                if parse_number(&token).is_some() || self.constants.contains_key(&token) {
+                    self.tokens.push_front(Token { text: token, line: self.line });
+                    let byte = self.byte()?;
+                    self.emit_byte(byte);
+                } else if is_reserved(&token) || token == "{" || token == "}" {
+                    return self.error(format!("UNEXPECTED '{}'", token));
+                } else {
+                    self.instruction_to_label(Instruction::Call(0), token);
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn expand_macro(&mut self, definition: Macro) -> Result<(), AssembleError> {
+        self.expansions += 1;
+        if self.expansions > MAX_TOTAL_MACRO_EXPANSIONS {
+            return self.error(format!("MORE THAN {} MACRO EXPANSIONS IN THE PROGRAM", MAX_TOTAL_MACRO_EXPANSIONS));
+        }
+        let mut values = HashMap::new();
+        for argument in &definition.arguments {
+            values.insert(argument.clone(), self.next_token()?);
+        }
+        for token in definition.body.into_iter().rev() {
+            let text = values.get(&token.text).cloned().unwrap_or(token.text);
+            self.tokens.push_front(Token { text, line: self.line });
+        }
+        Ok(())
+    }
+
+    fn resolve_fixups(&mut self) -> Result<(), AssembleError> {
+        for fixup in std::mem::take(&mut self.fixups) {
+            let address = match self.labels.get(&fixup.label) {
+                Some(address) => *address,
+                None => {
+                    self.line = fixup.line;
+                    return self.error(format!("UNDEFINED LABEL '{}'", fixup.label));
+                },
+            };
+            if address > 0xFFF && !matches!(fixup.kind, FixupKind::Long) {
+                self.line = fixup.line;
+                return self.error(format!("LABEL '{}' IS OUT OF RANGE", fixup.label));
+            }
+            let offset = fixup.address - PROGRAM_START;
+            match fixup.kind {
+                FixupKind::Address => self.patch_jump(fixup.address, address),
+                FixupKind::UnpackHigh(nibble) => self.rom[offset] = (nibble << 4) | (address >> 8) as u8,
+                FixupKind::UnpackLow => self.rom[offset] = (address & 0xFF) as u8,
+                FixupKind::Long => self.rom[offset + 2..offset + 4].copy_from_slice(&(address as u16).to_be_bytes()),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_binary_operator(token: &str) -> bool {
+    matches!(token, "+" | "-" | "*" | "/" | "%" | "&" | "|" | "^" | "<<" | ">>" | "pow" | "min" | "max" |
+                    "<" | ">" | "<=" | ">=" | "==" | "!=")
+}
+
+// compiles Octo source into a ROM that is loaded at 0x200
+pub fn compile(source: &str) -> Result<Vec<u8>, AssembleError> {
+    let mut compiler = Compiler::new(source);
+    while !compiler.tokens.is_empty() {
+        compiler.statement()?;
+        if compiler.here > XO_MEMORY_SIZE {
+            return compiler.error("PROGRAM DOES NOT FIT IN MEMORY".to_string());
+        }
+    }
+    if !compiler.branches.is_empty() {
+        return compiler.error("'begin' WITHOUT 'end'".to_string());
+    }
+    if !compiler.loops.is_empty() {
+        return compiler.error("'loop' WITHOUT 'again'".to_string());
+    }
+    compiler.start(None);
+    compiler.resolve_fixups()?;
+    Ok(compiler.rom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> String {
+        compile(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn jumps_to_main_unless_it_comes_first() {
+        assert_eq!(compile(": main v0 := 1").unwrap(), [0x60, 0x01]);
+        assert_eq!(compile(": data 1 2 : main v0 := 1").unwrap(), [0x12, 0x04, 0x01, 0x02, 0x60, 0x01]);
+    }
+
+    #[test]
+    fn compares_by_subtracting_into_vf() {
+        assert_eq!(compile(": main if v1 < v2 then v0 := 1").unwrap(), [0x8F, 0x10, 0x8F, 0x25, 0x4F, 0x00, 0x60, 0x01]);
+        assert_eq!(compile(": main if v1 >= 5 then v0 := 1").unwrap(), [0x6F, 0x05, 0x8F, 0x17, 0x4F, 0x01, 0x60, 0x01]);
+    }
+
+    #[test]
+    fn compiles_blocks_and_loops_into_jumps() {
+        let source = ": main if v0 == 1 begin v1 := 2 else v1 := 3 end";
+        assert_eq!(compile(source).unwrap(), [0x30, 0x01, 0x12, 0x08, 0x61, 0x02, 0x12, 0x0A, 0x61, 0x03]);
+        let source = ": main loop v0 += 1 while v0 != 5 again";
+        assert_eq!(compile(source).unwrap(), [0x70, 0x01, 0x40, 0x05, 0x12, 0x08, 0x12, 0x00]);
+    }
+
+    #[test]
+    fn unpacks_labels_and_evaluates_right_to_left() {
+        let source = "
+            : main
+                :unpack 0xA data
+                :next target v2 := 0
+                i := target
+                :calc twice { 2 * 3 + 4 }
+                v3 := twice
+            : data
+        ";
+        assert_eq!(compile(source).unwrap(), [0x60, 0xA2, 0x61, 0x0A, 0x62, 0x00, 0xA2, 0x05, 0x63, 0x0E]);
+    }
+
+    #[test]
+    fn expands_macros_with_their_arguments() {
+        let source = ":macro set-both a b { v0 := a v1 := b } : main set-both 1 2 set-both 3 4";
+        assert_eq!(compile(source).unwrap(), [0x60, 0x01, 0x61, 0x02, 0x60, 0x03, 0x61, 0x04]);
+    }
+
+    #[test]
+    fn reaches_labels_past_0xfff_with_long() {
+        let rom = compile(": main i := long data :org 0x1000 : data 1 2").unwrap();
+        assert_eq!(rom.len(), 0xE02);
+        assert_eq!(rom[..4], [0xF0, 0x00, 0x10, 0x00]);
+        assert_eq!(rom[0xE00..], [0x01, 0x02]);
+        assert_eq!(error(": main i := data :org 0x1000 : data 1 2"), "LINE 1: LABEL 'data' IS OUT OF RANGE");
+    }
+
+    #[test]
+    fn reports_undefined_labels() {
+        assert_eq!(error(": main\njump nowhere"), "LINE 2: UNDEFINED LABEL 'nowhere'");
+        assert_eq!(error(": start v0 := 1"), "LINE 1: UNDEFINED LABEL 'main'");
+    }
+
+    #[test]
+    fn stops_a_macro_that_expands_itself_forever() {
+        assert_eq!(error(":macro forever { forever } : main forever"), "LINE 1: MORE THAN 100000 MACRO EXPANSIONS IN THE PROGRAM");
+    }
+}
@@ -1,16 +1,94 @@
+use std::fmt;
 use rand::Rng;
 
-type Stack<T> = Vec<T>; 
-pub enum Chip8EmulatorError {
+use crate::instruction::DecodeError;
+
+pub type Stack<T> = Vec<T>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8ErrorKind {
+    InvalidInstruction(DecodeError),
     StackOverflow,
+    StackUnderflow,
+    MemoryOutOfBounds(usize),
+    ProgramCounterOutOfBounds,
+    FlagsNotSaved,
+}
+
+// what went wrong, and where: `opcode` is None when the instruction itself couldn't be fetched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chip8EmulatorError {
+    pub program_counter: usize,
+    pub opcode: Option<u16>,
+    pub kind: Chip8ErrorKind,
+}
+
+impl fmt::Display for Chip8ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8ErrorKind::InvalidInstruction(error) => write!(f, "{}", error),
+            Chip8ErrorKind::StackOverflow => write!(f, "STACK OVERFLOW"),
+            Chip8ErrorKind::StackUnderflow => write!(f, "NO RETURN ADDRESS FOUND"),
+            Chip8ErrorKind::MemoryOutOfBounds(address) => write!(f, "MEMORY ACCESS OUT OF BOUNDS ({:#05X})", address),
+            Chip8ErrorKind::ProgramCounterOutOfBounds => write!(f, "PROGRAM COUNTER OUT OF BOUNDS"),
+            Chip8ErrorKind::FlagsNotSaved => write!(f, "FAILED TO SAVE THE RPL FLAGS"),
+        }
+    }
+}
+
+impl fmt::Display for Chip8EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.opcode {
+            Some(opcode) => write!(f, "{} AT {:#05X} (OPCODE {:04X})", self.kind, self.program_counter, opcode),
+            None => write!(f, "{} AT {:#05X}", self.kind, self.program_counter),
+        }
+    }
+}
+
+impl std::error::Error for Chip8EmulatorError {}
+
+pub const STACK_CAPACITY: usize = 12;
+
+// xorshift64*, its whole state is a single number so it can be saved with the rest of the machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Random {
+    state: u64,
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Random {
+    pub fn new() -> Random {
+        Random::from_seed(rand::thread_rng().gen())
+    }
+
+    pub fn from_seed(seed: u64) -> Random {
+        // the generator is stuck at zero, so replace it with any other seed
+        let state = if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed };
+        Random { state }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
 }
-const STACK_CAPACITY: usize = 12;
 
 pub fn add_instruction(registers: &mut [u8; 16], index: usize, value: u8)
 {
     let (result, is_overflow) = (*registers.get(index).expect("NON VALID REGISTER")).overflowing_add(value);
-    registers[0xF as usize] = is_overflow.into();
     registers[index] = result;
+    registers[0xF] = is_overflow.into(); // the flag is written last, so it wins when index is vf
 }
 
 pub fn add_no_overflow_instruction(registers: &mut [u8; 16], index: usize, value: u8)
@@ -22,20 +100,22 @@ pub fn add_no_overflow_instruction(registers: &mut [u8; 16], index: usize, value
 pub fn sub_instruction(registers: &mut [u8; 16], index: usize, value: u8)
 {
     let (result, is_borrow) = (*registers.get(index).expect("NON VALID REGISTER")).overflowing_sub(value);
-    registers[0xF as usize] = (!is_borrow).into();
     registers[index] = result;
+    registers[0xF] = (!is_borrow).into();
 }
 
 pub fn shl_instruction(registers: &mut [u8; 16], index: usize)
 {
-    registers[0xF as usize] = ((*registers.get(index).expect("NON VALID REGISTER")) & 0b1000_0000) >> 7; // get the msb in r1, shift it 7 places right to get 1 or 0
-    registers[index] = (*registers.get(index).expect("NON VALID REGISTER")) << 1;
+    let value = *registers.get(index).expect("NON VALID REGISTER");
+    registers[index] = value << 1;
+    registers[0xF] = (value & 0b1000_0000) >> 7; // get the msb in r1, shift it 7 places right to get 1 or 0
 }
 
 pub fn shr_instruction(registers: &mut [u8; 16], index: usize)
 {
-    registers[0xF as usize] = (*registers.get(index).expect("NON VALID REGISTER")) & 0b0000_0001; // get the lsb in r1 in a form of 1 or 0
-    registers[index] = (*registers.get(index).expect("NON VALID REGISTER")) >> 1;
+    let value = *registers.get(index).expect("NON VALID REGISTER");
+    registers[index] = value >> 1;
+    registers[0xF] = value & 0b0000_0001; // get the lsb in r1 in a form of 1 or 0
 }
 
 pub fn jump_instruction(pc: &mut usize, next_address: u16)
@@ -43,10 +123,10 @@ pub fn jump_instruction(pc: &mut usize, next_address: u16)
     *pc = next_address as usize;
 }
 
-pub fn call_instruction(pc: &mut usize, next_address: u16, stack: &mut Stack<u16>) -> Result<(), Chip8EmulatorError>
+pub fn call_instruction(pc: &mut usize, next_address: u16, stack: &mut Stack<u16>) -> Result<(), Chip8ErrorKind>
 {
     if stack.len() >= STACK_CAPACITY {
-        return Err(Chip8EmulatorError::StackOverflow);
+        return Err(Chip8ErrorKind::StackOverflow);
     }
 
     stack.push(*pc as u16);
@@ -54,13 +134,12 @@ pub fn call_instruction(pc: &mut usize, next_address: u16, stack: &mut Stack<u16
     Ok(())
 }
 
-pub fn jump_v0_instruction(registers: [u8; 16], pc: &mut usize, next_address: u16)
+pub fn jump_offset_instruction(offset: u8, pc: &mut usize, next_address: u16)
 {
-    *pc = (registers[0] as u16 + next_address) as usize;
+    *pc = (offset as u16 + next_address) as usize;
 }
 
-pub fn rand_instruction(registers: &mut [u8; 16], index: usize, value: u8)
+pub fn rand_instruction(registers: &mut [u8; 16], index: usize, value: u8, random: &mut Random)
 {
-    let mut random = rand::thread_rng();
-    registers[index] = random.gen_range(0..=255) & value;
+    registers[index] = random.next_byte() & value;
 }
\ No newline at end of file
@@ -0,0 +1,118 @@
+use std::env;
+
+use crossterm::style::Color;
+
+pub const THEMES: [&str; 5] = ["default", "green", "amber", "lcd", "high-contrast"];
+
+// the steps of the 6x6x6 colour cube of 256-colour terminals
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    // "#RRGGBB" or "RRGGBB"
+    pub fn parse(text: &str) -> Option<Rgb> {
+        let hex = text.strip_prefix('#').unwrap_or(text);
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).ok();
+        Some(Rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+
+    // the closest of the 256 colours, from the colour cube or the grey ramp
+    pub fn to_ansi256(self) -> u8 {
+        let nearest_level = |value: u8| {
+            (0..CUBE_LEVELS.len()).min_by_key(|index| (i32::from(CUBE_LEVELS[*index]) - i32::from(value)).abs()).unwrap_or(0)
+        };
+        let (r, g, b) = (nearest_level(self.0), nearest_level(self.1), nearest_level(self.2));
+        let cube = Rgb(CUBE_LEVELS[r], CUBE_LEVELS[g], CUBE_LEVELS[b]);
+
+        let average = (u32::from(self.0) + u32::from(self.1) + u32::from(self.2)) / 3;
+        let grey_index = (average.saturating_sub(8) / 10).min(23) as u8;
+        let grey_level = 8 + grey_index * 10;
+        let grey = Rgb(grey_level, grey_level, grey_level);
+
+        if self.distance(grey) < self.distance(cube) {
+            232 + grey_index
+        } else {
+            16 + 36 * r as u8 + 6 * g as u8 + b as u8
+        }
+    }
+
+    fn distance(self, other: Rgb) -> u32 {
+        let channel = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2) as u32;
+        channel(self.0, other.0) + channel(self.1, other.1) + channel(self.2, other.2)
+    }
+}
+
+// what the terminal can show, truecolor terminals announce themselves in $COLORTERM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+}
+
+impl ColorDepth {
+    pub fn detect() -> ColorDepth {
+        match env::var("COLORTERM") {
+            Ok(value) if value == "truecolor" || value == "24bit" => ColorDepth::TrueColor,
+            _ => ColorDepth::Ansi256,
+        }
+    }
+}
+
+// the colour of every pixel value: off, the first plane, the second plane, and both planes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [Rgb; 4],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette { colors: [Rgb(0, 0, 0), Rgb(255, 255, 255), Rgb(255, 135, 0), Rgb(135, 95, 0)] }
+    }
+}
+
+impl Palette {
+    pub fn green() -> Palette {
+        Palette { colors: [Rgb(8, 24, 8), Rgb(51, 255, 51), Rgb(26, 140, 26), Rgb(179, 255, 179)] }
+    }
+
+    pub fn amber() -> Palette {
+        Palette { colors: [Rgb(26, 15, 0), Rgb(255, 176, 0), Rgb(153, 106, 0), Rgb(255, 217, 138)] }
+    }
+
+    // the four greens of an old handheld's LCD, dark pixels on a light background
+    pub fn lcd() -> Palette {
+        Palette { colors: [Rgb(155, 188, 15), Rgb(15, 56, 15), Rgb(139, 172, 15), Rgb(48, 98, 48)] }
+    }
+
+    pub fn high_contrast() -> Palette {
+        Palette { colors: [Rgb(0, 0, 0), Rgb(255, 255, 255), Rgb(255, 255, 0), Rgb(0, 255, 255)] }
+    }
+
+    pub fn theme(name: &str) -> Option<Palette> {
+        match name {
+            "default" => Some(Palette::default()),
+            "green" => Some(Palette::green()),
+            "amber" => Some(Palette::amber()),
+            "lcd" => Some(Palette::lcd()),
+            "high-contrast" => Some(Palette::high_contrast()),
+            _ => None,
+        }
+    }
+
+    pub fn rgb(&self, color: u8) -> Rgb {
+        self.colors[usize::from(color & 3)]
+    }
+
+    pub fn terminal_color(&self, color: u8, depth: ColorDepth) -> Color {
+        let Rgb(r, g, b) = self.rgb(color);
+        match depth {
+            ColorDepth::TrueColor => Color::Rgb { r, g, b },
+            ColorDepth::Ansi256 => Color::AnsiValue(self.rgb(color).to_ansi256()),
+        }
+    }
+}
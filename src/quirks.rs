@@ -0,0 +1,145 @@
+use crate::chip8::{MEMORY_SIZE, XO_MEMORY_SIZE};
+
+// the instruction set a program is written for, every platform extends the previous one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Platform {
+    #[default]
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+pub const PLATFORMS: [&str; 3] = ["chip8", "schip", "xochip"];
+
+impl Platform {
+    // how save states store the platform
+    pub fn to_byte(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_byte(byte: u8) -> Option<Platform> {
+        match byte {
+            0 => Some(Platform::Chip8),
+            1 => Some(Platform::SuperChip),
+            2 => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Platform> {
+        match name {
+            "chip8" => Some(Platform::Chip8),
+            "schip" => Some(Platform::SuperChip),
+            "xochip" => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::cosmac_vip(),
+            Platform::SuperChip => Quirks::super_chip(),
+            Platform::XoChip => Quirks::xo_chip(),
+        }
+    }
+
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::XoChip => XO_MEMORY_SIZE,
+            _ => MEMORY_SIZE,
+        }
+    }
+
+    // roughly how fast programs for the platform expect the CPU to be, in instructions per 60 Hz frame
+    pub fn instructions_per_frame(self) -> u32 {
+        match self {
+            Platform::Chip8 => 15,
+            Platform::SuperChip => 30,
+            Platform::XoChip => 100,
+        }
+    }
+}
+
+// behaviour that differs between the platforms chip-8 programs were written for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    // 8XY6/8XYE shift VY into VX, instead of shifting VX in place
+    pub shift_vy: bool,
+    // BNNN jumps to NNN + VX, where X is the high nibble of NNN, instead of NNN + V0
+    pub jump_vx: bool,
+    // FX55/FX65 leave I pointing after the last register
+    pub increment_i: bool,
+    // 8XY1/8XY2/8XY3 clear VF
+    pub vf_reset: bool,
+    // DXYN cuts sprites off at the screen edges, instead of wrapping them around
+    pub clip_sprites: bool,
+}
+
+pub const PRESETS: [&str; 4] = ["vip", "chip48", "schip", "xochip"];
+pub const QUIRK_NAMES: [&str; 5] = ["shift", "jump", "memory", "vf-reset", "clip"];
+
+impl Quirks {
+    pub fn cosmac_vip() -> Quirks {
+        Quirks { shift_vy: true, jump_vx: false, increment_i: true, vf_reset: true, clip_sprites: true }
+    }
+
+    pub fn chip48() -> Quirks {
+        Quirks { shift_vy: false, jump_vx: true, increment_i: false, vf_reset: false, clip_sprites: true }
+    }
+
+    // SUPER-CHIP kept every CHIP-48 quirk covered here
+    pub fn super_chip() -> Quirks {
+        Quirks::chip48()
+    }
+
+    pub fn xo_chip() -> Quirks {
+        Quirks { shift_vy: true, jump_vx: false, increment_i: true, vf_reset: false, clip_sprites: false }
+    }
+
+    pub fn preset(name: &str) -> Option<Quirks> {
+        match name {
+            "vip" => Some(Quirks::cosmac_vip()),
+            "chip48" => Some(Quirks::chip48()),
+            "schip" => Some(Quirks::super_chip()),
+            "xochip" => Some(Quirks::xo_chip()),
+            _ => None,
+        }
+    }
+
+    // one bit per quirk, in the order of QUIRK_NAMES, for save states
+    pub fn to_bits(self) -> u8 {
+        [self.shift_vy, self.jump_vx, self.increment_i, self.vf_reset, self.clip_sprites]
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (bit, enabled)| bits | (u8::from(*enabled) << bit))
+    }
+
+    pub fn from_bits(bits: u8) -> Option<Quirks> {
+        if bits >> QUIRK_NAMES.len() != 0 {
+            return None;
+        }
+        let enabled = |bit: usize| bits & (1 << bit) != 0;
+        Some(Quirks { shift_vy: enabled(0), jump_vx: enabled(1), increment_i: enabled(2), vf_reset: enabled(3), clip_sprites: enabled(4) })
+    }
+
+    // toggles a single quirk by the name used on the command line
+    pub fn set(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        let quirk = match name {
+            "shift" => &mut self.shift_vy,
+            "jump" => &mut self.jump_vx,
+            "memory" => &mut self.increment_i,
+            "vf-reset" => &mut self.vf_reset,
+            "clip" => &mut self.clip_sprites,
+            _ => return Err(format!("unknown quirk {}, expected one of {}", name, QUIRK_NAMES.join(", "))),
+        };
+        *quirk = enabled;
+        Ok(())
+    }
+}
+
+// programs for the original interpreter are the ones most likely to be called plain chip-8
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::cosmac_vip()
+    }
+}
@@ -0,0 +1,288 @@
+use std::io::{self, Write};
+
+use crossterm::cursor::MoveTo;
+use crossterm::queue;
+use crossterm::style::{Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{Clear, ClearType};
+
+use crate::palette::{ColorDepth, Palette};
+use crate::screen::Screen;
+
+pub const RENDERERS: [&str; 5] = ["halfblock", "braille", "emoji", "ascii", "headless"];
+
+// a braille character has two columns of four dots, this is the bit of each dot
+const BRAILLE_DOTS: [[u8; 2]; 4] = [
+    [0x01, 0x08],
+    [0x02, 0x10],
+    [0x04, 0x20],
+    [0x40, 0x80],
+];
+
+// ' ' for off, then the first plane, the second plane and both
+const ASCII_PIXELS: [char; 4] = [' ', '#', '+', '@'];
+
+// draws the screen once per frame, the frontend decides where the output goes
+pub trait Renderer {
+    fn render(&mut self, screen: &Screen, output: &mut dyn Write) -> io::Result<()>;
+
+    // how many terminal rows the screen takes, so the frontend can write under it
+    fn rows(&self, screen: &Screen) -> usize;
+}
+
+// the emoji and ASCII renderers have fixed looks, the others draw in the palette's colours
+pub fn from_name(name: &str, palette: Palette, depth: ColorDepth) -> Option<Box<dyn Renderer>> {
+    match name {
+        "halfblock" => Some(Box::new(HalfBlockRenderer::with_palette(palette, depth))),
+        "braille" => Some(Box::new(BrailleRenderer::with_palette(palette, depth))),
+        "emoji" => Some(Box::new(EmojiRenderer::new())),
+        "ascii" => Some(Box::new(AsciiRenderer::new())),
+        "headless" => Some(Box::new(HeadlessRenderer)),
+        _ => None,
+    }
+}
+
+fn color_at(screen: &Screen, x: usize, y: usize) -> u8 {
+    screen.get_color(&(x as u8), &(y as u8)).unwrap_or(0)
+}
+
+// what the terminal shows, so a frame only rewrites the character cells that changed
+struct Cells<T> {
+    cells: Vec<T>,
+    width: usize,
+}
+
+impl<T: Copy + PartialEq> Cells<T> {
+    fn new() -> Cells<T> {
+        Cells { cells: Vec::new(), width: 0 }
+    }
+
+    // `columns` is how many terminal columns a cell takes
+    fn update<W, F>(&mut self, width: usize, cells: Vec<T>, columns: usize, output: &mut W, mut draw: F) -> io::Result<()>
+        where W: Write, F: FnMut(&mut W, T) -> io::Result<()>
+    {
+        let full_redraw = self.width != width || self.cells.len() != cells.len();
+        if full_redraw {
+            queue!(output, ResetColor, Clear(ClearType::All))?;
+        }
+        let mut drawn = false;
+        for (row, row_cells) in cells.chunks(width.max(1)).enumerate() {
+            // the cursor only moves when a cell is skipped
+            let mut cursor_at = None;
+            for (x, cell) in row_cells.iter().enumerate() {
+                if !full_redraw && self.cells[row * width + x] == *cell {
+                    continue;
+                }
+                if cursor_at != Some(x) {
+                    queue!(output, MoveTo((x * columns) as u16, row as u16))?;
+                }
+                draw(output, *cell)?;
+                cursor_at = Some(x + 1);
+                drawn = true;
+            }
+        }
+        if drawn {
+            queue!(output, ResetColor)?;
+            output.flush()?;
+        }
+
+        self.cells = cells;
+        self.width = width;
+        Ok(())
+    }
+}
+
+// draws two rows of pixels per character cell, '▀' in the colour of the upper pixel on the background
+// colour of the lower one
+pub struct HalfBlockRenderer {
+    cells: Cells<(u8, u8)>, // (upper, lower) colour
+    palette: Palette,
+    depth: ColorDepth,
+}
+
+impl Default for HalfBlockRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HalfBlockRenderer {
+    pub fn new() -> HalfBlockRenderer {
+        HalfBlockRenderer::with_palette(Palette::default(), ColorDepth::Ansi256)
+    }
+
+    pub fn with_palette(palette: Palette, depth: ColorDepth) -> HalfBlockRenderer {
+        HalfBlockRenderer { cells: Cells::new(), palette, depth }
+    }
+}
+
+impl Renderer for HalfBlockRenderer {
+    fn rows(&self, screen: &Screen) -> usize {
+        screen.height().div_ceil(2)
+    }
+
+    fn render(&mut self, screen: &Screen, mut output: &mut dyn Write) -> io::Result<()> {
+        let width = screen.width();
+        let cells = (0..self.rows(screen))
+            .flat_map(|row| (0..width).map(move |x| (x, row)))
+            .map(|(x, row)| (color_at(screen, x, row * 2), color_at(screen, x, row * 2 + 1)))
+            .collect();
+        let (palette, depth) = (self.palette, self.depth);
+        let mut colors = None;
+        self.cells.update(width, cells, 1, &mut output, |output, cell| {
+            if colors != Some(cell) {
+                queue!(output, SetForegroundColor(palette.terminal_color(cell.0, depth)),
+                               SetBackgroundColor(palette.terminal_color(cell.1, depth)))?;
+                colors = Some(cell);
+            }
+            queue!(output, Print('▀'))
+        })
+    }
+}
+
+// packs 2x4 pixels into one braille character, so even 128x64 fits in 64 columns,
+// a dot is raised when its pixel is lit in any plane, and a character takes the colour of the planes lit in it
+pub struct BrailleRenderer {
+    cells: Cells<(u8, u8)>, // the raised dots and their colour
+    palette: Palette,
+    depth: ColorDepth,
+}
+
+impl Default for BrailleRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BrailleRenderer {
+    pub fn new() -> BrailleRenderer {
+        BrailleRenderer::with_palette(Palette::default(), ColorDepth::Ansi256)
+    }
+
+    pub fn with_palette(palette: Palette, depth: ColorDepth) -> BrailleRenderer {
+        BrailleRenderer { cells: Cells::new(), palette, depth }
+    }
+}
+
+impl Renderer for BrailleRenderer {
+    fn rows(&self, screen: &Screen) -> usize {
+        screen.height().div_ceil(4)
+    }
+
+    fn render(&mut self, screen: &Screen, mut output: &mut dyn Write) -> io::Result<()> {
+        let width = screen.width().div_ceil(2);
+        let cells = (0..self.rows(screen))
+            .flat_map(|row| (0..width).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                let (mut dots, mut color) = (0, 0);
+                for (dy, row_dots) in BRAILLE_DOTS.iter().enumerate() {
+                    for (dx, dot) in row_dots.iter().enumerate() {
+                        let pixel = color_at(screen, column * 2 + dx, row * 4 + dy);
+                        if pixel != 0 {
+                            dots |= dot;
+                            color |= pixel;
+                        }
+                    }
+                }
+                (dots, color)
+            })
+            .collect();
+        let (palette, depth) = (self.palette, self.depth);
+        let mut current_color = None;
+        self.cells.update(width, cells, 1, &mut output, |output, (dots, color)| {
+            if current_color != Some(color) {
+                queue!(output, SetForegroundColor(palette.terminal_color(color, depth)),
+                               SetBackgroundColor(palette.terminal_color(0, depth)))?;
+                current_color = Some(color);
+            }
+            let character = char::from_u32(0x2800 + u32::from(dots)).expect("NOT A BRAILLE CHARACTER");
+            queue!(output, Print(character))
+        })
+    }
+}
+
+// the original look, one emoji per pixel, which needs two columns for each
+pub struct EmojiRenderer {
+    cells: Cells<u8>, // the colour of each pixel
+}
+
+impl Default for EmojiRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EmojiRenderer {
+    pub fn new() -> EmojiRenderer {
+        EmojiRenderer { cells: Cells::new() }
+    }
+}
+
+impl Renderer for EmojiRenderer {
+    fn rows(&self, screen: &Screen) -> usize {
+        screen.height()
+    }
+
+    fn render(&mut self, screen: &Screen, mut output: &mut dyn Write) -> io::Result<()> {
+        let width = screen.width();
+        let cells = (0..self.rows(screen))
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| color_at(screen, x, y))
+            .collect();
+        self.cells.update(width, cells, 2, &mut output, |output, color| {
+            let emoji = match color {
+                0 => "⬛",
+                1 => "⬜",
+                2 => "🟧",
+                _ => "🟫",
+            };
+            queue!(output, Print(emoji))
+        })
+    }
+}
+
+// one character per pixel, for terminals without Unicode or colours
+pub struct AsciiRenderer {
+    cells: Cells<u8>, // the colour of each pixel
+}
+
+impl Default for AsciiRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsciiRenderer {
+    pub fn new() -> AsciiRenderer {
+        AsciiRenderer { cells: Cells::new() }
+    }
+}
+
+impl Renderer for AsciiRenderer {
+    fn rows(&self, screen: &Screen) -> usize {
+        screen.height()
+    }
+
+    fn render(&mut self, screen: &Screen, mut output: &mut dyn Write) -> io::Result<()> {
+        let width = screen.width();
+        let cells = (0..self.rows(screen))
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| color_at(screen, x, y))
+            .collect();
+        self.cells.update(width, cells, 1, &mut output, |output, color| {
+            queue!(output, Print(ASCII_PIXELS[usize::from(color & 3)]))
+        })
+    }
+}
+
+// draws nothing, for benchmarks and runs without a terminal
+pub struct HeadlessRenderer;
+
+impl Renderer for HeadlessRenderer {
+    fn rows(&self, _screen: &Screen) -> usize {
+        0
+    }
+
+    fn render(&mut self, _screen: &Screen, _output: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+}
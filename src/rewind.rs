@@ -0,0 +1,187 @@
+use std::collections::VecDeque;
+
+use crate::chip8::Chip8;
+use crate::savestate::SaveStateError;
+
+pub const DEFAULT_REWIND_CAPACITY: usize = 10000;
+
+// how to get from a snapshot back to the one before it: the length of the older state, its bytes
+// that differ from the newer one, and the memory bytes that changed in between with their old values,
+// states grow and shrink when the resolution changes
+type Delta = (usize, Vec<(usize, u8)>, Vec<(usize, u8)>);
+
+// a ring of machine snapshots, only the newest is kept whole and the others are
+// stored as the few bytes that changed, memory is not part of the snapshots but followed
+// through the addresses the machine wrote, which keeps per-instruction history cheap
+pub struct RewindBuffer {
+    newest: Option<Vec<u8>>, // without memory
+    deltas: VecDeque<Delta>,
+    memory: Vec<u8>, // memory as it was at the newest snapshot
+    stale: Vec<usize>, // addresses where the machine may differ from `memory` without having written them
+    capacity: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize) -> RewindBuffer {
+        RewindBuffer {
+            newest: None,
+            deltas: VecDeque::new(),
+            memory: Vec::new(),
+            stale: Vec::new(),
+            capacity,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len() + usize::from(self.newest.is_some())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.memory.clear();
+        self.stale.clear();
+    }
+
+    pub fn record(&mut self, chip8: &mut Chip8) {
+        let writes = chip8.take_memory_writes();
+        // a different memory size can't be rewound across, the history starts over
+        if self.memory.len() != chip8.memory().len() {
+            self.clear();
+            self.memory = chip8.memory().to_vec();
+        }
+        let memory_changes = self.sync_memory(chip8, writes);
+
+        let snapshot = chip8.save_state_without_memory();
+        if let Some(newest) = self.newest.take() {
+            let changes = newest.iter().enumerate()
+                .filter(|(offset, old)| snapshot.get(*offset) != Some(old))
+                .map(|(offset, old)| (offset, *old))
+                .collect();
+            self.deltas.push_back((newest.len(), changes, memory_changes));
+            if self.deltas.len() >= self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.newest = Some(snapshot);
+    }
+
+    // puts the machine back to the newest snapshot and forgets it, false when there is none
+    pub fn rewind(&mut self, chip8: &mut Chip8) -> Result<bool, SaveStateError> {
+        if self.newest.is_none() || self.memory.len() != chip8.memory().len() {
+            return Ok(false);
+        }
+        let snapshot = self.newest.take().expect("CHECKED ABOVE");
+        // the machine is left untouched, and the older snapshots can't be reached without this one
+        if let Err(error) = chip8.load_state_without_memory(&snapshot) {
+            self.clear();
+            return Err(error);
+        }
+
+        // undo the writes since the snapshot, then take them so they aren't seen as new ones
+        match chip8.take_memory_writes() {
+            Some(writes) => {
+                let memory = chip8.memory_mut();
+                for address in writes.into_iter().chain(self.stale.drain(..)) {
+                    memory[address] = self.memory[address];
+                }
+            },
+            None => chip8.memory_mut().copy_from_slice(&self.memory),
+        }
+        chip8.take_memory_writes();
+        self.stale.clear();
+
+        if let Some((length, changes, memory_changes)) = self.deltas.pop_back() {
+            let mut older = snapshot;
+            older.resize(length, 0);
+            for (offset, value) in changes {
+                older[offset] = value;
+            }
+            self.newest = Some(older);
+            // the machine keeps the newer memory until it is rewound again or runs on
+            for (address, value) in memory_changes {
+                self.memory[address] = value;
+                self.stale.push(address);
+            }
+        }
+        Ok(true)
+    }
+
+    // brings `memory` up to the machine's, returning the bytes that changed with their old values
+    fn sync_memory(&mut self, chip8: &Chip8, writes: Option<Vec<usize>>) -> Vec<(usize, u8)> {
+        let memory = chip8.memory();
+        let addresses: Box<dyn Iterator<Item = usize>> = match writes {
+            Some(writes) => Box::new(writes.into_iter().chain(self.stale.drain(..))),
+            None => Box::new(0..memory.len()),
+        };
+        let mut changes = Vec::new();
+        for address in addresses {
+            if self.memory[address] != memory[address] {
+                changes.push((address, self.memory[address]));
+                self.memory[address] = memory[address];
+            }
+        }
+        self.stale.clear();
+        changes
+    }
+}
+
+impl Default for RewindBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_REWIND_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // counts up in V0 and stores every value one byte further on, so each step writes memory
+    const PROGRAM: [u8; 10] = [0x60, 0x00, 0xA3, 0x00, 0x70, 0x01, 0xF0, 0x55, 0x12, 0x04];
+
+    // records and steps `steps` times, returning the whole state before every step
+    fn run(chip8: &mut Chip8, history: &mut RewindBuffer, steps: usize) -> Vec<Vec<u8>> {
+        (0..steps).map(|_| {
+            history.record(chip8);
+            let state = chip8.save_state();
+            chip8.step().unwrap();
+            state
+        }).collect()
+    }
+
+    #[test]
+    fn rewinds_through_every_recorded_state() {
+        let mut chip8 = Chip8::new();
+        chip8.load_program(&PROGRAM).unwrap();
+        let mut history = RewindBuffer::default();
+        let states = run(&mut chip8, &mut history, 50);
+        for state in states.iter().rev() {
+            assert!(history.rewind(&mut chip8).unwrap());
+            assert!(chip8.save_state() == *state);
+        }
+        assert!(!history.rewind(&mut chip8).unwrap());
+    }
+
+    #[test]
+    fn rewinds_after_running_on_from_a_rewound_state() {
+        let mut chip8 = Chip8::new();
+        chip8.load_program(&PROGRAM).unwrap();
+        let mut history = RewindBuffer::default();
+        let mut states = run(&mut chip8, &mut history, 30);
+        for _ in 0..10 {
+            history.rewind(&mut chip8).unwrap();
+        }
+        states.truncate(20);
+        // V0 restarts the count, so memory goes back to values it held before
+        chip8.set_register(0, 0);
+        states.extend(run(&mut chip8, &mut history, 15));
+        for state in states.iter().rev() {
+            assert!(history.rewind(&mut chip8).unwrap());
+            assert!(chip8.save_state() == *state);
+        }
+    }
+}
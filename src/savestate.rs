@@ -0,0 +1,128 @@
+use std::fmt;
+use std::io;
+
+pub const MAGIC: &[u8; 4] = b"CH8S";
+pub const VERSION: u16 = 7;
+
+#[derive(Debug)]
+pub enum SaveStateError {
+    Io(io::Error),
+    NotASaveState,
+    UnsupportedVersion(u16),
+    Truncated,
+    Invalid(&'static str),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::Io(error) => write!(f, "SAVE STATE I/O ERROR: {}", error),
+            SaveStateError::NotASaveState => write!(f, "NOT A SAVE STATE"),
+            SaveStateError::UnsupportedVersion(version) => write!(f, "UNSUPPORTED SAVE STATE VERSION {}", version),
+            SaveStateError::Truncated => write!(f, "SAVE STATE IS TRUNCATED"),
+            SaveStateError::Invalid(what) => write!(f, "SAVE STATE HAS A NON VALID {}", what),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+impl From<io::Error> for SaveStateError {
+    fn from(error: io::Error) -> Self {
+        SaveStateError::Io(error)
+    }
+}
+
+// every value is stored big-endian, like the opcodes in memory
+#[derive(Default)]
+pub struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        let mut writer = StateWriter { bytes: Vec::new() };
+        writer.write_bytes(MAGIC);
+        writer.write_u16(VERSION);
+        writer
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+pub struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    // checks the header, and returns a reader positioned right after it
+    pub fn new(bytes: &'a [u8]) -> Result<StateReader<'a>, SaveStateError> {
+        let mut reader = StateReader { bytes };
+        if reader.read_bytes(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(SaveStateError::NotASaveState);
+        }
+        let version = reader.read_u16()?;
+        if version != VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+        Ok(reader)
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], SaveStateError> {
+        if self.bytes.len() < length {
+            return Err(SaveStateError::Truncated);
+        }
+        let (read, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(read)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, SaveStateError> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, SaveStateError> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from_be_bytes(bytes.try_into().expect("READ 4 BYTES")))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, SaveStateError> {
+        let bytes = self.read_bytes(8)?;
+        Ok(u64::from_be_bytes(bytes.try_into().expect("READ 8 BYTES")))
+    }
+
+    // a state with bytes left over was written by something else
+    pub fn finish(self) -> Result<(), SaveStateError> {
+        if !self.bytes.is_empty() {
+            return Err(SaveStateError::Invalid("LENGTH"));
+        }
+        Ok(())
+    }
+}
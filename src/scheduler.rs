@@ -0,0 +1,120 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::chip8::Chip8;
+use crate::opcodes::Chip8EmulatorError;
+use crate::timers::FRAME_DURATION;
+
+// how closely emulated frames follow the wall clock
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Pacing {
+    // 60 frames per second, like the real hardware
+    #[default]
+    RealTime,
+    // this many times faster than real time
+    FastForward(u32),
+    // as fast as the host can go, for benchmarking
+    Uncapped,
+}
+
+// runs the CPU in 60 Hz frames of a fixed number of instructions, the timers tick once at the start of every frame
+pub struct Scheduler {
+    instructions_per_frame: u32,
+    pacing: Pacing,
+    cycle: u32, // instructions executed in the current frame
+    frames: u64,
+    instructions: u64,
+    next_frame: Option<Instant>,
+}
+
+impl Scheduler {
+    pub fn new(instructions_per_frame: u32) -> Scheduler {
+        Scheduler {
+            instructions_per_frame: instructions_per_frame.max(1),
+            pacing: Pacing::RealTime,
+            cycle: 0,
+            frames: 0,
+            instructions: 0,
+            next_frame: None,
+        }
+    }
+
+    pub fn instructions_per_frame(&self) -> u32 {
+        self.instructions_per_frame
+    }
+
+    pub fn pacing(&self) -> Pacing {
+        self.pacing
+    }
+
+    pub fn set_pacing(&mut self, pacing: Pacing) {
+        self.pacing = pacing;
+        self.next_frame = None;
+    }
+
+    // frames and instructions run so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    // true between two frames, when the next step starts a new one
+    pub fn is_frame_start(&self) -> bool {
+        self.cycle == 0
+    }
+
+    // executes one instruction, ticking the timers first when it starts a frame
+    pub fn step(&mut self, chip8: &mut Chip8) -> Result<bool, Chip8EmulatorError> {
+        if self.cycle == 0 {
+            chip8.tick_timers();
+        }
+        let running = chip8.step()?;
+        self.instructions += 1;
+        self.cycle += 1;
+        if self.cycle == self.instructions_per_frame {
+            self.cycle = 0;
+            self.frames += 1;
+        }
+        Ok(running)
+    }
+
+    // executes the rest of the current frame, false once the program has ended
+    pub fn run_frame(&mut self, chip8: &mut Chip8) -> Result<bool, Chip8EmulatorError> {
+        loop {
+            if !self.step(chip8)? {
+                return Ok(false);
+            }
+            if self.is_frame_start() {
+                return Ok(true);
+            }
+        }
+    }
+
+    // how long a frame takes on the wall clock
+    fn frame_duration(&self) -> Option<Duration> {
+        match self.pacing {
+            Pacing::RealTime => Some(FRAME_DURATION),
+            Pacing::FastForward(factor) => Some(FRAME_DURATION / factor.max(1)),
+            Pacing::Uncapped => None,
+        }
+    }
+
+    // sleeps until the next frame is due, a host that fell behind doesn't try to catch up
+    pub fn wait_for_next_frame(&mut self) {
+        let frame_duration = match self.frame_duration() {
+            Some(frame_duration) => frame_duration,
+            None => return,
+        };
+        let now = Instant::now();
+        let due = self.next_frame.unwrap_or(now) + frame_duration;
+        if due > now {
+            thread::sleep(due - now);
+            self.next_frame = Some(due);
+        } else {
+            self.next_frame = Some(now);
+        }
+    }
+}
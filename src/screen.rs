@@ -1,44 +1,138 @@
+use std::io::{self, Write};
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PixelState {
     On,
     Off,
 }
+pub const SCREEN_WIDTH: usize = 64;
+pub const SCREEN_HEIGHT: usize = 32;
+// SUPER-CHIP high resolution mode
+pub const HIGH_RES_WIDTH: usize = 128;
+pub const HIGH_RES_HEIGHT: usize = 64;
+// XO-CHIP draws on two bitplanes, classic programs only ever use the first one
+pub const ALL_PLANES: u8 = 0b11;
+
 pub struct Screen {
-    pixels: [[PixelState; 64]; 32],
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>, // one bit per plane, so a colour from 0 to 3
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Screen {
     pub fn new() -> Screen {
+        Screen::with_size(SCREEN_WIDTH, SCREEN_HEIGHT)
+    }
+
+    pub fn with_size(width: usize, height: usize) -> Screen {
         Screen {
-            pixels: [[PixelState::Off; 64]; 32],
+            width,
+            height,
+            pixels: vec![0; width * height],
         }
     }
 
-    pub fn display_pixels(&self) {
-        for pixel_row in self.pixels {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn is_high_res(&self) -> bool {
+        self.width == HIGH_RES_WIDTH
+    }
+
+    // switching resolutions clears the screen
+    pub fn resize(&mut self, width: usize, height: usize) {
+        *self = Screen::with_size(width, height);
+    }
+
+    // one emoji per pixel
+    pub fn write_pixels<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        for pixel_row in self.pixels.chunks(self.width) {
             for pixel in pixel_row {
                 match pixel {
-                    PixelState::Off => print!("⬛"),
-                    PixelState::On => print!("⬜"),
+                    0 => write!(output, "⬛")?,
+                    1 => write!(output, "⬜")?,
+                    2 => write!(output, "🟧")?,
+                    _ => write!(output, "🟫")?,
                 }
             }
-            println!("");
+            writeln!(output)?;
         }
+        Ok(())
     }
 
     pub fn clear_screen(&mut self) {
-        for pixel_row in self.pixels {
-            for mut _pixel in pixel_row {
-                _pixel = PixelState::Off;
+        self.clear_planes(ALL_PLANES);
+    }
+
+    pub fn clear_planes(&mut self, planes: u8) {
+        for pixel in &mut self.pixels {
+            *pixel &= !planes;
+        }
+    }
+
+    pub fn scroll_down(&mut self, rows: usize, planes: u8) {
+        self.shift(0, rows as isize, planes);
+    }
+
+    pub fn scroll_up(&mut self, rows: usize, planes: u8) {
+        self.shift(0, -(rows as isize), planes);
+    }
+
+    pub fn scroll_right(&mut self, columns: usize, planes: u8) {
+        self.shift(columns as isize, 0, planes);
+    }
+
+    pub fn scroll_left(&mut self, columns: usize, planes: u8) {
+        self.shift(-(columns as isize), 0, planes);
+    }
+
+    // moves the given planes, whatever scrolls in from the edges is off
+    fn shift(&mut self, dx: isize, dy: isize, planes: u8) {
+        let old = self.pixels.clone();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (source_x, source_y) = (x as isize - dx, y as isize - dy);
+                let source = if (0..self.width as isize).contains(&source_x) && (0..self.height as isize).contains(&source_y) {
+                    old[source_y as usize * self.width + source_x as usize]
+                } else {
+                    0
+                };
+                let pixel = &mut self.pixels[y * self.width + x];
+                *pixel = (*pixel & !planes) | (source & planes);
             }
         }
     }
 
+    // a pixel is on when it is lit in any plane
     pub fn get_pixel(&self, x: &u8, y: &u8) -> Option<PixelState> {
-        self.pixels.get(*y as usize)?.get(*x as usize).copied()
+        match self.get_color(x, y)? {
+            0 => Some(PixelState::Off),
+            _ => Some(PixelState::On),
+        }
     }
 
-    pub fn set_pixel(&mut self, x: &u8, y: &u8, value: PixelState) {
-        self.pixels[*y as usize][*x as usize] = value
+    pub fn get_color(&self, x: &u8, y: &u8) -> Option<u8> {
+        let (x, y) = (*x as usize, *y as usize);
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(self.pixels[y * self.width + x])
+    }
+
+    pub fn set_color(&mut self, x: &u8, y: &u8, color: u8) {
+        let (x, y) = (*x as usize, *y as usize);
+        assert!(x < self.width && y < self.height, "COORDINATES OUT OF BOUND");
+        self.pixels[y * self.width + x] = color & ALL_PLANES
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,105 @@
+use std::fs::File;
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::path::Path;
+
+use crate::palette::Palette;
+use crate::screen::Screen;
+
+pub const FORMATS: [&str; 3] = ["png", "ppm", "pbm"];
+
+// 64x32 becomes 512x256, big enough to read in a bug report
+pub const DEFAULT_SCALE: usize = 8;
+// 128x64 becomes 8192x4096, larger images are only a way to run out of memory
+pub const MAX_SCALE: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Ppm, // colour, binary P6
+    Pbm, // black and white, binary P4
+}
+
+impl ImageFormat {
+    // picked by the file's extension, in either case
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "ppm" => Some(ImageFormat::Ppm),
+            "pbm" => Some(ImageFormat::Pbm),
+            _ => None,
+        }
+    }
+}
+
+// the colour of every pixel of the image, each screen pixel blown up to `scale` x `scale`
+fn scaled_rows(screen: &Screen, scale: usize) -> impl Iterator<Item = Vec<u8>> + '_ {
+    let scale = scale.clamp(1, MAX_SCALE);
+    (0..screen.height() * scale).map(move |y| {
+        (0..screen.width() * scale)
+            .map(|x| screen.get_color(&((x / scale) as u8), &((y / scale) as u8)).unwrap_or(0))
+            .collect()
+    })
+}
+
+pub fn write_png<W: Write>(screen: &Screen, palette: &Palette, scale: usize, output: W) -> io::Result<()> {
+    let scale = scale.clamp(1, MAX_SCALE);
+    let (width, height) = (screen.width() * scale, screen.height() * scale);
+    let mut encoder = png::Encoder::new(output, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    let data: Vec<u8> = scaled_rows(screen, scale)
+        .flatten()
+        .flat_map(|color| {
+            let rgb = palette.rgb(color);
+            [rgb.0, rgb.1, rgb.2]
+        })
+        .collect();
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(())
+}
+
+pub fn write_ppm<W: Write>(screen: &Screen, palette: &Palette, scale: usize, mut output: W) -> io::Result<()> {
+    let scale = scale.clamp(1, MAX_SCALE);
+    write!(output, "P6\n{} {}\n255\n", screen.width() * scale, screen.height() * scale)?;
+    for row in scaled_rows(screen, scale) {
+        let bytes: Vec<u8> = row.iter().flat_map(|color| {
+            let rgb = palette.rgb(*color);
+            [rgb.0, rgb.1, rgb.2]
+        }).collect();
+        output.write_all(&bytes)?;
+    }
+    output.flush()
+}
+
+// PBM has no colours, a pixel lit in any plane is black as on paper
+pub fn write_pbm<W: Write>(screen: &Screen, scale: usize, mut output: W) -> io::Result<()> {
+    let scale = scale.clamp(1, MAX_SCALE);
+    write!(output, "P4\n{} {}\n", screen.width() * scale, screen.height() * scale)?;
+    for row in scaled_rows(screen, scale) {
+        // eight pixels per byte, the first in the highest bit, rows padded to whole bytes
+        let bytes: Vec<u8> = row.chunks(8).map(|pixels| {
+            pixels.iter().enumerate().fold(0u8, |byte, (bit, color)| {
+                if *color != 0 { byte | 0x80 >> bit } else { byte }
+            })
+        }).collect();
+        output.write_all(&bytes)?;
+    }
+    output.flush()
+}
+
+// writes the screen in the format the file's extension asks for
+pub fn save(screen: &Screen, palette: &Palette, scale: usize, path: &Path) -> io::Result<()> {
+    let format = ImageFormat::from_path(path).ok_or_else(|| io::Error::new(
+        ErrorKind::InvalidInput,
+        format!("UNKNOWN IMAGE FORMAT, EXPECTED ONE OF {}", FORMATS.join(", ")),
+    ))?;
+    let output = BufWriter::new(File::create(path)?);
+    match format {
+        ImageFormat::Png => write_png(screen, palette, scale, output),
+        ImageFormat::Ppm => write_ppm(screen, palette, scale, output),
+        ImageFormat::Pbm => write_pbm(screen, scale, output),
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+
+// at most this many seconds of samples wait to be played, older ones are dropped so sound can't lag behind
+const MAX_QUEUED_SECONDS: usize = 1;
+
+// plays mono samples on the default output device, the host pushes them as it renders them
+pub struct AudioDevice {
+    queue: Arc<Mutex<VecDeque<i16>>>,
+    sample_rate: u32,
+    _stream: Stream,
+}
+
+impl AudioDevice {
+    pub fn open() -> Result<AudioDevice, String> {
+        let device = cpal::default_host().default_output_device().ok_or("NO AUDIO DEVICE FOUND")?;
+        let supported = device.default_output_config().map_err(|error| format!("AUDIO DEVICE ERROR: {}", error))?;
+        let sample_format = supported.sample_format();
+        let config: StreamConfig = supported.into();
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+
+        let stream = match sample_format {
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, queue.clone()),
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, queue.clone()),
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, queue.clone()),
+            format => return Err(format!("UNSUPPORTED SAMPLE FORMAT {}", format)),
+        }?;
+        stream.play().map_err(|error| format!("AUDIO DEVICE ERROR: {}", error))?;
+
+        Ok(AudioDevice { queue, sample_rate: config.sample_rate.0, _stream: stream })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn push(&self, samples: &[i16]) {
+        let mut queue = self.queue.lock().expect("Failed to lock audio queue");
+        queue.extend(samples);
+        let limit = self.sample_rate as usize * MAX_QUEUED_SECONDS;
+        if queue.len() > limit {
+            let excess = queue.len() - limit;
+            queue.drain(..excess);
+        }
+    }
+}
+
+fn build_stream<T>(device: &cpal::Device, config: &StreamConfig, queue: Arc<Mutex<VecDeque<i16>>>) -> Result<Stream, String>
+    where T: SizedSample + FromSample<i16>
+{
+    let channels = usize::from(config.channels);
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            let mut queue = queue.lock().expect("Failed to lock audio queue");
+            // the same sample goes to every channel, and running dry plays silence
+            for frame in data.chunks_mut(channels) {
+                let sample = T::from_sample(queue.pop_front().unwrap_or(0));
+                frame.fill(sample);
+            }
+        },
+        |error| eprintln!("AUDIO DEVICE ERROR: {}", error),
+        None,
+    ).map_err(|error| format!("AUDIO DEVICE ERROR: {}", error))
+}
@@ -0,0 +1,148 @@
+use std::io::{self, Write};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers,
+    KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::cursor::{Hide, Show};
+use crossterm::execute;
+use crossterm::terminal::{self, disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+
+use chip8::keymap::KeyMap;
+use chip8::keypad::Keypad;
+
+// terminals that only report presses repeat them while a key is held, so a key counts as
+// released once it hasn't been repeated for this many frames, the first repeat only comes
+// after the autorepeat delay (500 ms on most systems, 660 ms by default on X11), so 42 frames
+// (700 ms) outlasts it and a held key doesn't let go before repeating starts
+const HOLD_FRAMES: u32 = 42;
+
+static RAW_MODE: AtomicBool = AtomicBool::new(false);
+static ALTERNATE_SCREEN: AtomicBool = AtomicBool::new(false);
+static RELEASE_EVENTS: AtomicBool = AtomicBool::new(false);
+
+// frontend actions bound to keys outside the keypad
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    Quit,
+    SaveState,
+    LoadState,
+    Screenshot,
+}
+
+// keyboard input in raw mode, with the display on the alternate screen so the shell's
+// scrollback is left alone, the terminal is restored when this is dropped, on panic,
+// or by calling `restore` before exiting the process
+pub struct Terminal {
+    keymap: KeyMap,
+    keys: [u32; 16], // frames left until a key counts as released
+    rewind: u32,
+}
+
+impl Terminal {
+    pub fn enter(keymap: KeyMap) -> io::Result<Terminal> {
+        enable_raw_mode()?;
+        RAW_MODE.store(true, Ordering::SeqCst);
+        execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+        ALTERNATE_SCREEN.store(true, Ordering::SeqCst);
+        // release events make held keys exact, where the terminal supports them
+        if terminal::supports_keyboard_enhancement().unwrap_or(false) {
+            execute!(io::stdout(), PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+            RELEASE_EVENTS.store(true, Ordering::SeqCst);
+        }
+
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore();
+            default_hook(info);
+        }));
+        Ok(Terminal { keymap, keys: [0; 16], rewind: 0 })
+    }
+
+    // reads the pending key events and updates the keypad, called once per frame
+    pub fn poll(&mut self, keypad: &mut Keypad) -> io::Result<Vec<Hotkey>> {
+        let release_events = RELEASE_EVENTS.load(Ordering::SeqCst);
+        if !release_events {
+            for (key, frames) in self.keys.iter_mut().enumerate() {
+                *frames = frames.saturating_sub(1);
+                if *frames == 0 {
+                    keypad.release_key(key);
+                }
+            }
+            self.rewind = self.rewind.saturating_sub(1);
+        }
+
+        let mut hotkeys = Vec::new();
+        while event::poll(Duration::ZERO)? {
+            let key_event = match event::read()? {
+                Event::Key(key_event) => key_event,
+                _ => continue,
+            };
+            let held = match key_event.kind {
+                KeyEventKind::Press | KeyEventKind::Repeat => if release_events { 1 } else { HOLD_FRAMES },
+                KeyEventKind::Release => 0,
+            };
+            match key_event {
+                KeyEvent { code: KeyCode::Char('c'), modifiers: KeyModifiers::CONTROL, .. } |
+                KeyEvent { code: KeyCode::Esc, .. } => hotkeys.push(Hotkey::Quit),
+                KeyEvent { code: KeyCode::F(5), kind: KeyEventKind::Press, .. } => hotkeys.push(Hotkey::SaveState),
+                KeyEvent { code: KeyCode::F(9), kind: KeyEventKind::Press, .. } => hotkeys.push(Hotkey::LoadState),
+                KeyEvent { code: KeyCode::F(12), kind: KeyEventKind::Press, .. } => hotkeys.push(Hotkey::Screenshot),
+                KeyEvent { code: KeyCode::Backspace, .. } => self.rewind = held,
+                KeyEvent { code: KeyCode::Char(character), .. } => {
+                    if let Some(key) = self.keymap.key_for_char(character) {
+                        let key = usize::from(key);
+                        self.keys[key] = held;
+                        if held > 0 {
+                            keypad.press_key(key);
+                        } else {
+                            keypad.release_key(key);
+                        }
+                    }
+                },
+                _ => {},
+            }
+        }
+        Ok(hotkeys)
+    }
+
+    // puts the keys held right now back onto the keypad, after a save state or the rewind
+    // buffer replaced it with the keys held back then
+    pub fn restore_keys(&self, keypad: &mut Keypad) {
+        for (key, frames) in self.keys.iter().enumerate() {
+            if *frames > 0 {
+                keypad.press_key(key);
+            } else {
+                keypad.release_key(key);
+            }
+        }
+    }
+
+    // the rewind key is held
+    pub fn is_rewinding(&self) -> bool {
+        self.rewind > 0
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+// leaves raw mode and the alternate screen, safe to call more than once
+pub fn restore() {
+    if RELEASE_EVENTS.swap(false, Ordering::SeqCst) {
+        let _ = execute!(io::stdout(), PopKeyboardEnhancementFlags);
+    }
+    if ALTERNATE_SCREEN.swap(false, Ordering::SeqCst) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+    }
+    if RAW_MODE.swap(false, Ordering::SeqCst) {
+        let _ = disable_raw_mode();
+    }
+    let _ = io::stdout().flush();
+}
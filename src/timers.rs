@@ -1,6 +1,9 @@
 use core::time::Duration;
-use std::thread;
-use std::sync::{Arc, Mutex};
+
+// both timers count down at 60 Hz, once per frame
+pub const TIMER_FREQUENCY: u32 = 60;
+pub const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / TIMER_FREQUENCY as u64);
+
 pub struct DelayTimer {
     timer: u8,
 }
@@ -9,9 +12,27 @@ pub struct SoundTimer {
     timer: u8,
 }
 
+impl Default for DelayTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for SoundTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub trait Timer {
     fn get_timer(&self) -> u8;
     fn set_timer(&mut self, value: u8);
+
+    // one 60 Hz frame has passed
+    fn tick(&mut self) {
+        let value = self.get_timer();
+        self.set_timer(value.saturating_sub(1));
+    }
 }
 
 impl Timer for SoundTimer {
@@ -25,7 +46,7 @@ impl Timer for SoundTimer {
 
 impl SoundTimer {
     pub fn new() -> SoundTimer {
-        SoundTimer { timer: u8::MAX }
+        SoundTimer { timer: 0 }
     }
 }
 
@@ -40,27 +61,6 @@ impl Timer for DelayTimer {
 
 impl DelayTimer {
     pub fn new() -> DelayTimer {
-        DelayTimer { timer: u8::MAX }
+        DelayTimer { timer: 0 }
     }
 }
-
-pub fn decrement_timer(timer_arc: Arc<Mutex<Box<dyn Timer + Send>>>) {
-    loop {
-        // Lock the mutex to access the timer
-        let mut timer = timer_arc.lock().unwrap();
-
-        // Decrement the timer value by 60
-        let current_value = timer.get_timer();
-        if current_value > 60 {
-            timer.set_timer(current_value - 60);
-        } else {
-            timer.set_timer(0);
-        }
-
-        // Unlock the mutex to release the timer
-        drop(timer);
-
-        // Sleep for one second
-        thread::sleep(Duration::from_secs(1));
-    }
-}
\ No newline at end of file